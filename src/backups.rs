@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+use crate::config::write_atomic_with;
+use crate::retry::unix_now;
+use crate::tools::{Tool, set_executable, tools_dir};
+
+pub const KEEP_CHOICES: [usize; 5] = [0, 1, 3, 5, 10];
+
+pub fn backups_dir() -> PathBuf { tools_dir().join("backups") }
+
+// A previous managed binary. The file name carries everything: `<tool>-<unix time>-<version>[.exe]`,
+// so listing the folder is enough and no index can go stale.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup { pub path: PathBuf, pub version: String, pub saved: u64 }
+
+fn stem(tool: Tool) -> &'static str { tool.binary_name().trim_end_matches(".exe") }
+
+fn file_name(tool: Tool, saved: u64, version: &str) -> String {
+    let version: String = version.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' }).collect();
+    format!("{}-{}-{}{}", stem(tool), saved, version, if cfg!(windows) { ".exe" } else { "" })
+}
+
+// Newest first.
+pub fn list(tool: Tool) -> Vec<Backup> {
+    let prefix = format!("{}-", stem(tool));
+    let Ok(entries) = std::fs::read_dir(backups_dir()) else { return Vec::new() };
+    let mut backups: Vec<Backup> = entries.filter_map(|e| e.ok()).filter_map(|e| {
+        let name = e.file_name().to_string_lossy().to_string();
+        let (saved, version) = name.strip_prefix(&prefix)?.trim_end_matches(".exe").split_once('-')?;
+        Some(Backup { path: e.path(), version: version.to_string(), saved: saved.parse().ok()? })
+    }).collect();
+    backups.sort_by_key(|b| std::cmp::Reverse(b.saved));
+    backups
+}
+
+// Copies the managed binary aside before it is replaced, then drops all but the newest `keep`.
+pub fn save(tool: Tool, version: &str, keep: usize) -> Result<(), String> {
+    let current = tool.install_path();
+    if keep == 0 || !current.is_file() { return Ok(()); }
+    std::fs::create_dir_all(backups_dir()).map_err(|e| e.to_string())?;
+    std::fs::copy(&current, backups_dir().join(file_name(tool, unix_now(), version))).map_err(|e| format!("Backup failed: {}", e))?;
+    for old in list(tool).into_iter().skip(keep) { let _ = std::fs::remove_file(old.path); }
+    Ok(())
+}
+
+// Puts a backup back in place of the managed binary, which becomes a backup itself so the rollback
+// can be undone the same way. Returns the version restored.
+pub fn restore(tool: Tool, backup: &Path, current_version: &str, keep: usize) -> Result<String, String> {
+    let chosen = list(tool).into_iter().find(|b| b.path == backup).ok_or("Backup no longer exists")?;
+    let bytes = std::fs::read(backup).map_err(|e| e.to_string())?;
+    // Taken out of the list first, or saving the current binary could prune the very backup being restored
+    let _ = std::fs::remove_file(backup);
+    let swapped = write_atomic_with(&tool.install_path(), &bytes, |tmp| { set_executable(tmp)?; save(tool, current_version, keep) });
+    if let Err(e) = swapped {
+        if std::fs::write(backup, &bytes).is_ok() { let _ = set_executable(backup); }
+        return Err(e);
+    }
+    Ok(chosen.version)
+}
@@ -0,0 +1,244 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::types::{AdvOptions, DownloadItem, DownloadStatus, ProxyProtocol};
+use crate::retry::RetryPolicy;
+use crate::tools::{ToolChannels, ToolPaths, ToolSources};
+
+// Bump this whenever a stored field is renamed or changes meaning, and add a step to `migrate`.
+// Plain additions don't need a bump: `#[serde(default)]` fills them in on load.
+pub const CONFIG_VERSION: u64 = 1;
+
+// Answers with an empty 204, so a proxy check costs next to nothing
+pub const DEFAULT_PROXY_TEST_URL: &str = "https://www.gstatic.com/generate_204";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub version: u64,
+    pub settings: AdvOptions,
+    pub output_dir: Option<PathBuf>,
+    pub cookie_path: Option<PathBuf>,
+    pub proxy_protocol: ProxyProtocol,
+    pub manual_proxy: String,
+    pub max_concurrent: usize,
+    pub delete_partials_on_cancel: bool,
+    pub retry: RetryPolicy,
+    pub proxy_test_url: String,
+    pub proxy_check_concurrency: usize,
+    pub tool_sources: ToolSources,
+    pub tool_paths: ToolPaths,
+    pub tool_channels: ToolChannels,
+    pub keep_tool_backups: usize,
+    // Sources without checksums (evermeet, pinned ffmpeg builds) only install once this is on
+    pub allow_unverified_tools: bool,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            version: CONFIG_VERSION,
+            settings: AdvOptions::default(),
+            output_dir: None,
+            cookie_path: None,
+            proxy_protocol: ProxyProtocol::Socks5,
+            manual_proxy: String::new(),
+            max_concurrent: 3,
+            delete_partials_on_cancel: false,
+            retry: RetryPolicy::default(),
+            proxy_test_url: DEFAULT_PROXY_TEST_URL.to_string(),
+            proxy_check_concurrency: 8,
+            tool_sources: ToolSources::default(),
+            tool_paths: ToolPaths::default(),
+            tool_channels: ToolChannels::default(),
+            keep_tool_backups: 3,
+            allow_unverified_tools: false,
+        }
+    }
+}
+
+pub fn app_dir() -> PathBuf {
+    dirs::config_dir().unwrap_or_else(|| std::env::current_dir().unwrap_or_default()).join("yt-dlp-gui")
+}
+
+pub fn config_path() -> PathBuf { app_dir().join("config.json") }
+pub fn queue_path() -> PathBuf { app_dir().join("queue.json") }
+
+// Where a config file that couldn't be fully read is copied before the next save overwrites it.
+pub fn unreadable_config_path() -> PathBuf { app_dir().join("config.unreadable.json") }
+
+pub fn load_config() -> AppConfig {
+    let Ok(text) = std::fs::read_to_string(config_path()) else { return AppConfig::default() };
+    let (cfg, complete) = match serde_json::from_str::<Value>(&text) {
+        Ok(raw) => migrate(raw),
+        Err(_) => (AppConfig::default(), false),
+    };
+    if !complete { let _ = std::fs::copy(config_path(), unreadable_config_path()); }
+    cfg
+}
+
+pub fn save_config(cfg: &AppConfig) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(cfg).map_err(|e| e.to_string())?;
+    write_atomic(&config_path(), &json)
+}
+
+#[derive(Serialize)]
+struct QueueJournal<'a> { version: u64, next_id: usize, items: &'a [DownloadItem] }
+
+pub fn save_queue(items: &[DownloadItem], next_id: usize) -> Result<(), String> {
+    let json = serde_json::to_vec(&QueueJournal { version: CONFIG_VERSION, next_id, items }).map_err(|e| e.to_string())?;
+    write_atomic(&queue_path(), &json)
+}
+
+// Returns the restored queue and the next free id. Items are decoded one by one so a single
+// unreadable entry doesn't take the rest of the queue down with it.
+pub fn load_queue() -> (Vec<DownloadItem>, usize) {
+    let Some(raw) = std::fs::read_to_string(queue_path()).ok().and_then(|t| serde_json::from_str::<Value>(&t).ok()) else { return (Vec::new(), 0) };
+    let mut items: Vec<DownloadItem> = raw.get("items").and_then(|v| v.as_array()).map(|arr| {
+        arr.iter().filter_map(|v| serde_json::from_value(v.clone()).ok()).collect()
+    }).unwrap_or_default();
+    for item in items.iter_mut() {
+        // Whatever was running when we went down goes back in line; yt-dlp picks the .part file back up.
+        if matches!(item.status, DownloadStatus::Downloading) { item.status = DownloadStatus::Queued; }
+        item.speed = if matches!(item.status, DownloadStatus::Paused) { "Paused".into() } else { "-".into() };
+    }
+    let stored_next = raw.get("next_id").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+    let next_id = items.iter().map(|i| i.id + 1).max().unwrap_or(0).max(stored_next);
+    (items, next_id)
+}
+
+// Returns the config and whether every stored field could be read. Renamed or reshaped fields get a
+// step here that checks `raw["version"]` (0 for files written before it existed) and rewrites `raw`
+// before it is deserialized.
+fn migrate(raw: Value) -> (AppConfig, bool) {
+    let (mut cfg, complete) = match serde_json::from_value::<AppConfig>(raw.clone()) {
+        Ok(cfg) => (cfg, true),
+        Err(_) => (decode_by_field(&raw), false),
+    };
+    cfg.version = CONFIG_VERSION;
+    cfg.max_concurrent = cfg.max_concurrent.clamp(1, 50);
+    cfg.proxy_check_concurrency = cfg.proxy_check_concurrency.clamp(1, 64);
+    cfg.keep_tool_backups = cfg.keep_tool_backups.min(10);
+    (cfg, complete)
+}
+
+// One value this build can't read, say an enum variant from a newer version, shouldn't cost the rest
+// of the settings: start from the defaults and take the stored fields one at a time, keeping each that
+// decodes. A nested object or list that doesn't is taken apart the same way, so only the bad value
+// itself falls back to its default.
+fn decode_by_field(raw: &Value) -> AppConfig {
+    let mut merged = serde_json::to_value(AppConfig::default()).unwrap_or_default();
+    merge_fields(&mut merged, raw, &mut Vec::new());
+    serde_json::from_value(merged).unwrap_or_default()
+}
+
+fn merge_fields(merged: &mut Value, raw: &Value, path: &mut Vec<String>) {
+    let Some(fields) = raw.as_object() else { return };
+    for (key, value) in fields {
+        path.push(key.clone());
+        if !try_set(merged, path, value.clone()) {
+            if value.is_object() && lookup(merged, path).is_some_and(Value::is_object) {
+                merge_fields(merged, value, path);
+            } else if let Some(items) = value.as_array() {
+                // Keep the entries this build knows, e.g. all but a retry class added later
+                if try_set(merged, path, Value::Array(Vec::new())) {
+                    for item in items {
+                        let mut list = lookup(merged, path).and_then(Value::as_array).cloned().unwrap_or_default();
+                        list.push(item.clone());
+                        try_set(merged, path, Value::Array(list));
+                    }
+                }
+            }
+        }
+        path.pop();
+    }
+}
+
+// Puts `value` at `path` if the config still decodes with it there.
+fn try_set(merged: &mut Value, path: &[String], value: Value) -> bool {
+    let mut candidate = merged.clone();
+    *slot(&mut candidate, path) = value;
+    let ok = serde_json::from_value::<AppConfig>(candidate.clone()).is_ok();
+    if ok { *merged = candidate; }
+    ok
+}
+
+fn slot<'a>(value: &'a mut Value, path: &[String]) -> &'a mut Value { path.iter().fold(value, |v, key| &mut v[key.as_str()]) }
+
+fn lookup<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> { path.iter().try_fold(value, |v, key| v.get(key)) }
+
+// Write to a sibling temp file and rename over the target so a crash never leaves a half-written file.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), String> { write_atomic_with(path, bytes, |_| Ok(())) }
+
+// `write_atomic` with a step run on the finished temp file just before the swap, e.g. to mark it
+// executable. If the step fails the temp file is removed and `path` is left as it was.
+pub fn write_atomic_with(path: &Path, bytes: &[u8], before_swap: impl FnOnce(&Path) -> Result<(), String>) -> Result<(), String> {
+    if let Some(dir) = path.parent() { std::fs::create_dir_all(dir).map_err(|e| e.to_string())?; }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(&tmp, bytes).map_err(|e| e.to_string())?;
+    if let Err(e) = before_swap(&tmp).and_then(|_| std::fs::rename(&tmp, path).map_err(|e| e.to_string())) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::quality::{AudioCodecPref, ResolutionCap};
+    use crate::retry::RetryClass;
+    use crate::tools::UpdateChannel;
+    use crate::types::AudioFormat;
+
+    fn load(raw: Value) -> (AppConfig, bool) { migrate(raw) }
+
+    #[test]
+    fn a_complete_file_reads_as_is() {
+        let (cfg, complete) = load(json!({ "version": 1, "max_concurrent": 5, "settings": { "audio_fmt": "Flac" } }));
+        assert!(complete);
+        assert_eq!(cfg.max_concurrent, 5);
+        assert_eq!(cfg.settings.audio_fmt, AudioFormat::Flac);
+    }
+
+    #[test]
+    fn an_unknown_value_costs_only_itself() {
+        // As a newer version might write it: a format, a quality cap and a retry class this build doesn't have
+        let (cfg, complete) = load(json!({
+            "version": 1,
+            "max_concurrent": 7,
+            "settings": {
+                "audio_fmt": "Alac", "embed_subs": true, "sub_langs": "en,de", "rate_limit": "2M",
+                "quality": { "max_resolution": "P4320", "audio_codec": "Opus", "max_filesize": "2G" },
+            },
+            "retry": { "enabled": false, "max_attempts": 5, "classes": ["Network", "Captcha", "Proxy"] },
+            "tool_channels": { "ytdlp": { "channel": "Beta", "pinned_version": "2024.08.06" }, "ffmpeg": { "channel": "Nightly" } },
+            "keep_tool_backups": "lots",
+        }));
+        assert!(!complete);
+        assert_eq!(cfg.max_concurrent, 7);
+        assert_eq!(cfg.settings.audio_fmt, AudioFormat::None);
+        assert!(cfg.settings.embed_subs);
+        assert_eq!(cfg.settings.sub_langs, "en,de");
+        assert_eq!(cfg.settings.rate_limit, "2M");
+        assert_eq!(cfg.settings.quality.max_resolution, ResolutionCap::Any);
+        assert_eq!(cfg.settings.quality.audio_codec, AudioCodecPref::Opus);
+        assert_eq!(cfg.settings.quality.max_filesize, "2G");
+        assert!(!cfg.retry.enabled);
+        assert_eq!(cfg.retry.max_attempts, 5);
+        assert_eq!(cfg.retry.classes, [RetryClass::Network, RetryClass::Proxy]);
+        assert_eq!(cfg.tool_channels.ytdlp.channel, UpdateChannel::Stable);
+        assert_eq!(cfg.tool_channels.ytdlp.pinned_version, "2024.08.06");
+        assert_eq!(cfg.tool_channels.ffmpeg.channel, UpdateChannel::Nightly);
+        assert_eq!(cfg.keep_tool_backups, AppConfig::default().keep_tool_backups);
+    }
+
+    #[test]
+    fn values_out_of_range_are_clamped() {
+        let (cfg, _) = load(json!({ "max_concurrent": 0, "proxy_check_concurrency": 1000, "keep_tool_backups": 99 }));
+        assert_eq!((cfg.max_concurrent, cfg.proxy_check_concurrency, cfg.keep_tool_backups), (1, 64, 10));
+    }
+}
@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+use regex::Regex;
+use once_cell::sync::Lazy;
+
+static HTTP_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)HTTP Error (\d{3})").unwrap()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorKind { GeoBlocked, AgeRestricted, Private, Unavailable, RateLimited, Http(u16), Proxy, FfmpegMissing, DiskFull, Network, InvalidArgs, Unknown }
+
+impl ErrorKind {
+    pub fn icon(self) -> &'static str {
+        match self {
+            ErrorKind::GeoBlocked => "🌍", ErrorKind::AgeRestricted => "🔞", ErrorKind::Private => "🔒", ErrorKind::Unavailable => "🚫",
+            ErrorKind::RateLimited => "⏱", ErrorKind::Http(_) => "🌐", ErrorKind::Proxy => "🔌", ErrorKind::FfmpegMissing => "🧩",
+            ErrorKind::DiskFull => "💾", ErrorKind::Network => "📡", ErrorKind::InvalidArgs => "⚙", ErrorKind::Unknown => "❌",
+        }
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            ErrorKind::GeoBlocked => "Geo-blocked".into(),
+            ErrorKind::AgeRestricted => "Age-restricted".into(),
+            ErrorKind::Private => "Private video".into(),
+            ErrorKind::Unavailable => "Unavailable".into(),
+            ErrorKind::RateLimited => "Rate limited".into(),
+            ErrorKind::Http(code) => format!("HTTP {}", code),
+            ErrorKind::Proxy => "Proxy error".into(),
+            ErrorKind::FfmpegMissing => "ffmpeg missing".into(),
+            ErrorKind::DiskFull => "Disk full".into(),
+            ErrorKind::Network => "Network error".into(),
+            ErrorKind::InvalidArgs => "Invalid arguments".into(),
+            ErrorKind::Unknown => "Error".into(),
+        }
+    }
+
+    pub fn advice(self) -> &'static str {
+        match self {
+            ErrorKind::GeoBlocked => "Not available in your region. Try a proxy located in another country.",
+            ErrorKind::AgeRestricted => "The site wants a signed-in account. Load a cookies file exported from your browser.",
+            ErrorKind::Private => "Private or members-only. Only cookies from an account with access will work.",
+            ErrorKind::Unavailable => "The video was removed or never existed; retrying won't help.",
+            ErrorKind::RateLimited => "The site is throttling requests. Wait a while, lower Max Concurrent or spread over proxies.",
+            ErrorKind::Http(403) => "The server refused the request. Updating yt-dlp (Update Tools) usually fixes this.",
+            ErrorKind::Http(404) => "The server no longer has this file; the link may have expired. Retry to fetch a fresh one.",
+            ErrorKind::Http(_) => "The server returned an error. Retry later.",
+            ErrorKind::Proxy => "The proxy couldn't be reached or refused the connection. Check it or use another one.",
+            ErrorKind::FfmpegMissing => "ffmpeg is needed to merge or convert. Install it with Update Tools.",
+            ErrorKind::DiskFull => "No space left. Free some space or choose another output folder.",
+            ErrorKind::Network => "The connection failed. Check your network and retry.",
+            ErrorKind::InvalidArgs => "yt-dlp rejected its arguments. Check Custom Arguments on the Advanced tab.",
+            ErrorKind::Unknown => "Open the log for the full yt-dlp output.",
+        }
+    }
+}
+
+// A failed download: what kind of failure it was and the yt-dlp line that said so.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredError")]
+pub struct DownloadError { pub kind: ErrorKind, pub message: String }
+
+impl DownloadError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self { DownloadError { kind, message: message.into() } }
+}
+
+// Queue files written before failures were classified stored only the message.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredError { Typed { kind: ErrorKind, message: String }, Message(String) }
+
+impl From<StoredError> for DownloadError {
+    fn from(e: StoredError) -> Self {
+        match e {
+            StoredError::Typed { kind, message } => DownloadError { kind, message },
+            StoredError::Message(message) => classify(&[message], None),
+        }
+    }
+}
+
+// Classifies a failure from the stderr lines yt-dlp printed (oldest first) and its exit code. The
+// most specific ERROR line wins; checks run from narrow to broad, e.g. a proxy timeout is a proxy
+// problem before it is a network one.
+pub fn classify(lines: &[String], exit_code: Option<i32>) -> DownloadError {
+    let message = lines.iter().rev().find(|l| l.starts_with("ERROR:")).or(lines.last()).cloned()
+        .unwrap_or_else(|| "Unknown error (Non-zero exit)".to_string());
+    let text = lines.join("\n").to_lowercase();
+    let has = |needles: &[&str]| needles.iter().any(|n| text.contains(n));
+
+    let kind = if has(&["no space left on device", "errno 28", "not enough space on the disk", "disk quota exceeded"]) {
+        ErrorKind::DiskFull
+    } else if has(&["ffmpeg is not installed", "ffmpeg not found", "ffprobe and ffmpeg not found", "ffmpeg could not be found"]) {
+        ErrorKind::FfmpegMissing
+    } else if has(&["proxyerror", "unable to connect to proxy", "tunnel connection failed", "proxy authentication required", "socks5 proxy", "socksv5", "socks4 proxy", "connection to proxy"]) {
+        ErrorKind::Proxy
+    } else if has(&["not available in your country", "geo restriction", "geo-restrict", "not available from your location", "blocked it in your country", "not made this video available in your country"]) {
+        ErrorKind::GeoBlocked
+    } else if has(&["confirm your age", "age-restricted", "age restricted", "inappropriate for some users", "age verification"]) {
+        ErrorKind::AgeRestricted
+    } else if has(&["private video", "video is private", "members-only", "join this channel to get access", "available to this channel's members", "requires a subscription"]) {
+        ErrorKind::Private
+    } else if has(&["http error 429", "too many requests", "rate-limit", "rate limit", "confirm you're not a bot", "confirm you’re not a bot"]) {
+        ErrorKind::RateLimited
+    } else if let Some(code) = HTTP_RE.captures(&text).and_then(|c| c[1].parse().ok()) {
+        ErrorKind::Http(code)
+    } else if has(&["video unavailable", "has been removed", "no longer available", "account associated with this video has been terminated", "does not exist", "unsupported url"]) {
+        ErrorKind::Unavailable
+    } else if has(&["timed out", "connection reset", "connection refused", "connection aborted", "getaddrinfo failed", "name or service not known", "temporary failure in name resolution", "network is unreachable", "nodename nor servname", "remote end closed connection", "incompleteread"]) {
+        ErrorKind::Network
+    } else if exit_code == Some(2) || has(&["error: no such option", "yt-dlp: error:"]) {
+        // 2 is yt-dlp's exit code for a command line it couldn't parse
+        ErrorKind::InvalidArgs
+    } else {
+        ErrorKind::Unknown
+    };
+    DownloadError { kind, message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Case { kind: ErrorKind, exit: Option<i32>, lines: Vec<String> }
+
+    fn cases() -> Vec<Case> {
+        let text = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/yt-dlp-errors.txt")).unwrap();
+        let mut cases: Vec<Case> = Vec::new();
+        for line in text.lines() {
+            if let Some(header) = line.strip_prefix("== ") {
+                let (kind, exit) = match header.split_once(" | exit ") { Some((k, e)) => (k, Some(e.parse().unwrap())), None => (header, None) };
+                cases.push(Case { kind: serde_json::from_str(kind).unwrap(), exit, lines: Vec::new() });
+            } else if let Some(case) = cases.last_mut() {
+                // The download stream drops blank stderr lines before classifying
+                if !line.trim().is_empty() { case.lines.push(line.to_string()); }
+            }
+        }
+        cases
+    }
+
+    #[test]
+    fn fixtures_classify_as_labelled() {
+        let cases = cases();
+        for case in &cases {
+            assert_eq!(classify(&case.lines, case.exit).kind, case.kind, "{:?}", case.lines);
+        }
+        // Every kind has at least one real message behind it
+        for kind in [ErrorKind::GeoBlocked, ErrorKind::AgeRestricted, ErrorKind::Private, ErrorKind::Unavailable, ErrorKind::RateLimited,
+            ErrorKind::Http(403), ErrorKind::Proxy, ErrorKind::FfmpegMissing, ErrorKind::DiskFull, ErrorKind::Network, ErrorKind::InvalidArgs, ErrorKind::Unknown] {
+            assert!(cases.iter().any(|c| c.kind == kind), "no fixture for {:?}", kind);
+        }
+    }
+
+    #[test]
+    fn message_is_the_last_error_line() {
+        let lines = vec!["WARNING: [youtube] Falling back to generic n function search".to_string(), "ERROR: first".to_string(), "ERROR: second".to_string(), "some trailing note".to_string()];
+        assert_eq!(classify(&lines, Some(1)).message, "ERROR: second");
+        assert_eq!(classify(&["just text".to_string()], Some(1)).message, "just text");
+        assert_eq!(classify(&[], Some(1)).message, "Unknown error (Non-zero exit)");
+    }
+
+    #[test]
+    fn narrower_kinds_win_over_broader_ones() {
+        let kind = |s: &str| classify(&[s.to_string()], Some(1)).kind;
+        // Disk full beats a failed conversion that mentions ffmpeg
+        assert_eq!(kind("ERROR: ffmpeg not found; also No space left on device"), ErrorKind::DiskFull);
+        // A proxy timeout is a proxy problem before a network one
+        assert_eq!(kind("ERROR: Unable to connect to proxy: timed out"), ErrorKind::Proxy);
+        // 429 is rate limiting, not a generic HTTP error
+        assert_eq!(kind("ERROR: HTTP Error 429: Too Many Requests"), ErrorKind::RateLimited);
+        // An HTTP code beats the "does not exist" wording that often follows a 404
+        assert_eq!(kind("ERROR: HTTP Error 404: Not Found: the page does not exist"), ErrorKind::Http(404));
+        // Exit code 2 only counts when nothing more specific was said
+        assert_eq!(classify(&["ERROR: Video unavailable".to_string()], Some(2)).kind, ErrorKind::Unavailable);
+    }
+
+    #[test]
+    fn legacy_string_errors_are_classified_on_load() {
+        let err: DownloadError = serde_json::from_str(r#""ERROR: unable to download video data: HTTP Error 429: Too Many Requests""#).unwrap();
+        assert_eq!(err, DownloadError::new(ErrorKind::RateLimited, "ERROR: unable to download video data: HTTP Error 429: Too Many Requests"));
+        let err: DownloadError = serde_json::from_str(r#""Unknown error (Non-zero exit)""#).unwrap();
+        assert_eq!(err.kind, ErrorKind::Unknown);
+
+        let typed = DownloadError::new(ErrorKind::Http(503), "ERROR: HTTP Error 503: Service Unavailable");
+        let json = serde_json::to_string(&typed).unwrap();
+        assert_eq!(serde_json::from_str::<DownloadError>(&json).unwrap(), typed);
+        // The stored kind is kept even if the message would classify differently today
+        let stored: DownloadError = serde_json::from_str(r#"{"kind":"Network","message":"ERROR: Private video"}"#).unwrap();
+        assert_eq!(stored.kind, ErrorKind::Network);
+    }
+}
@@ -0,0 +1,123 @@
+use serde_json::Value;
+
+// One entry of yt-dlp's `formats` list, reduced to what the format browser shows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatInfo {
+    pub id: String,
+    pub ext: String,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub fps: Option<f64>,
+    pub vcodec: Option<String>,
+    pub acodec: Option<String>,
+    pub tbr: Option<f64>,
+    pub filesize: Option<u64>,
+    pub dynamic_range: Option<String>,
+    pub note: Option<String>,
+}
+
+impl FormatInfo {
+    pub fn has_video(&self) -> bool { self.vcodec.is_some() }
+    pub fn has_audio(&self) -> bool { self.acodec.is_some() }
+    pub fn is_hdr(&self) -> bool { self.dynamic_range.as_deref().is_some_and(|r| r != "SDR") }
+
+    pub fn resolution(&self) -> String {
+        match (self.width, self.height) {
+            (Some(w), Some(h)) => format!("{}x{}", w, h),
+            (None, Some(h)) => format!("{}p", h),
+            _ if !self.has_video() => "audio only".into(),
+            _ => "?".into(),
+        }
+    }
+
+    pub fn codecs(&self) -> String {
+        match (&self.vcodec, &self.acodec) {
+            (Some(v), Some(a)) => format!("{} + {}", short_codec(v), short_codec(a)),
+            (Some(v), None) => short_codec(v),
+            (None, Some(a)) => short_codec(a),
+            (None, None) => "-".into(),
+        }
+    }
+
+    pub fn bitrate(&self) -> String { self.tbr.map(|t| format!("{:.0}k", t)).unwrap_or("-".into()) }
+
+    pub fn size(&self) -> String { self.filesize.map(human_size).unwrap_or("-".into()) }
+}
+
+// Storyboards and other image-only formats are dropped; they can't be downloaded as media.
+pub fn parse_formats(json: &Value) -> Vec<FormatInfo> {
+    let Some(list) = json.get("formats").and_then(|v| v.as_array()) else { return Vec::new() };
+    let codec = |f: &Value, key: &str| f.get(key).and_then(|v| v.as_str()).filter(|c| *c != "none").map(str::to_string);
+    list.iter().filter_map(|f| {
+        let id = f.get("format_id").and_then(|v| v.as_str())?;
+        let explicit_none = |key: &str| f.get(key).and_then(|v| v.as_str()) == Some("none");
+        if explicit_none("vcodec") && explicit_none("acodec") { return None; }
+        // Some extractors leave both codecs out; treat those as combined formats of unknown codec
+        let (vcodec, acodec) = match (codec(f, "vcodec"), codec(f, "acodec")) {
+            (None, None) => (Some("?".to_string()), Some("?".to_string())),
+            pair => pair,
+        };
+        Some(FormatInfo {
+            id: id.to_string(),
+            ext: f.get("ext").and_then(|v| v.as_str()).unwrap_or("?").to_string(),
+            width: f.get("width").and_then(|v| v.as_u64()),
+            height: f.get("height").and_then(|v| v.as_u64()),
+            fps: f.get("fps").and_then(|v| v.as_f64()),
+            vcodec,
+            acodec,
+            tbr: f.get("tbr").and_then(|v| v.as_f64()),
+            filesize: f.get("filesize").or(f.get("filesize_approx")).and_then(|v| v.as_f64()).map(|s| s as u64),
+            dynamic_range: f.get("dynamic_range").and_then(|v| v.as_str()).map(str::to_string),
+            note: f.get("format_note").and_then(|v| v.as_str()).map(str::to_string),
+        })
+    }).collect()
+}
+
+// The `-f` value for an explicit pick; `None` leaves format selection to the download options.
+pub fn format_selector(video: Option<&str>, audio: Option<&str>) -> Option<String> {
+    match (video, audio) {
+        (Some(v), Some(a)) => Some(format!("{}+{}", v, a)),
+        (Some(f), None) | (None, Some(f)) => Some(f.to_string()),
+        (None, None) => None,
+    }
+}
+
+fn short_codec(c: &str) -> String { c.split('.').next().unwrap_or(c).to_string() }
+
+pub fn human_size(bytes: u64) -> String {
+    let b = bytes as f64;
+    if b >= 1024.0 * 1024.0 * 1024.0 { format!("{:.2}GiB", b / (1024.0 * 1024.0 * 1024.0)) }
+    else if b >= 1024.0 * 1024.0 { format!("{:.1}MiB", b / (1024.0 * 1024.0)) }
+    else { format!("{:.0}KiB", b / 1024.0) }
+}
+
+// State of the format browser dialog for one queued item.
+#[derive(Debug, Clone, Default)]
+pub struct FormatBrowser {
+    pub item_id: usize,
+    pub url: String,
+    pub title: String,
+    pub loading: bool,
+    pub error: Option<String>,
+    pub video: Option<String>,
+    pub audio: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_without_codecs_count_as_combined() {
+        let json = serde_json::json!({"formats": [
+            {"format_id": "hls-720p", "ext": "mp4", "height": 720},
+            {"format_id": "sb0", "ext": "mhtml", "vcodec": "none", "acodec": "none"},
+            {"format_id": "140", "ext": "m4a", "vcodec": "none", "acodec": "mp4a.40.2"},
+        ]});
+        let formats = parse_formats(&json);
+        assert_eq!(formats.iter().map(|f| f.id.as_str()).collect::<Vec<_>>(), ["hls-720p", "140"]);
+        // Pairing this with bestaudio would fail on sites that have no separate audio stream
+        assert!(formats[0].has_video() && formats[0].has_audio());
+        assert!(!formats[1].has_video() && formats[1].has_audio());
+    }
+}
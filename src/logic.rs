@@ -1,25 +1,82 @@
 use iced::Subscription;
-use std::path::PathBuf;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::mpsc;
 use tokio::process::Command as TokioCommand;
 use regex::Regex;
 use once_cell::sync::Lazy;
-use crate::types::{Message, AdvOptions, AudioFormat, VideoType};
+use serde::{Deserialize, Serialize};
+use crate::types::Message;
+use crate::command::YtDlpCommand;
+use crate::progress::{Progress, StageTracker, DOWNLOAD_PREFIX, POSTPROCESS_PREFIX};
+use crate::logs::LogFile;
+use crate::proxy::redact_credentials;
+use crate::errors::{DownloadError, ErrorKind, classify};
+use crate::formats::{FormatInfo, parse_formats};
 
-static PROGRESS_RE: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"(\d+(?:\.\d+)?)%\s+of\s+([~0-9a-zA-Z\.]+)(?:\s+at\s+([0-9a-zA-Z\./]+))?").unwrap()
+static DEST_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^\[\w+\] (?:Destination: (.+)|Merging formats into "(.+)")$"#).unwrap()
 });
 
-pub async fn analyze_url_task(url: String, proxy: Option<String>, cookie: Option<PathBuf>) -> Result<serde_json::Value, String> {
-    let local_yt = std::env::current_dir().unwrap_or_default().join("yt-dlp.exe");
-    let cmd_name = if local_yt.exists() { local_yt.to_string_lossy().to_string() } else { "yt-dlp".to_string() };
+// Running yt-dlp processes, keyed by item id. The subscription only owns the stdout reader, so a
+// cancel can take the child out of here, kill its whole tree and wait for it to exit.
+struct Job { child: tokio::process::Child, files: WrittenFiles }
+static JOBS: Lazy<Mutex<HashMap<usize, Job>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
-    let mut cmd = TokioCommand::new(cmd_name);
-    cmd.arg("-J").arg("--flat-playlist").arg(&url);
+// What a download has written, as far as its stdout tells: every destination yt-dlp announced and the
+// one it was still writing when last seen. The others finished, so only that one is a partial file.
+// A paused item keeps this in the queue file, so a cancel after a restart can still clean up.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WrittenFiles { pub destinations: Vec<PathBuf>, pub in_progress: Option<PathBuf> }
 
-    if let Some(p) = proxy { cmd.arg("--proxy").arg(p); }
-    if let Some(c) = cookie { cmd.arg("--cookies").arg(c); }
+impl WrittenFiles {
+    pub fn feed(&mut self, line: &str) {
+        if let Some(caps) = DEST_RE.captures(line) {
+            if let Some(path) = caps.get(1).or(caps.get(2)) {
+                let path = PathBuf::from(path.as_str());
+                if !self.destinations.contains(&path) { self.destinations.push(path.clone()); }
+                self.in_progress = Some(path);
+            }
+            return;
+        }
+        // A download reports finished once its .part is renamed, a post-processor once its output is complete
+        let done = |prefix: &str| line.strip_prefix(prefix).is_some_and(|rest| rest.trim_start().starts_with("finished|"));
+        if done(DOWNLOAD_PREFIX) || done(POSTPROCESS_PREFIX) { self.in_progress = None; }
+    }
+
+    pub fn is_empty(&self) -> bool { self.destinations.is_empty() }
+}
+
+// How a stop ended: the files deleted, and those left in place for a resume (none once deleted).
+#[derive(Debug, Clone, Default)]
+pub struct Stopped { pub removed: usize, pub kept: WrittenFiles }
+
+pub async fn analyze_url_task(program: PathBuf, url: String, proxy: Option<String>, cookie: Option<PathBuf>, playlist_items: Option<String>) -> Result<serde_json::Value, String> {
+    let mut args = vec!["--flat-playlist".to_string()];
+    if let Some(items) = playlist_items { args.push("--playlist-items".into()); args.push(items); }
+    let json = run_ytdlp_json(&program, &args, &url, proxy.as_deref(), cookie.as_deref()).await?;
+    // A single video normally comes back fully extracted; if the extractor returned a stub, ask again without flattening
+    if json.get("entries").is_none() && json.get("formats").is_none() {
+        return run_ytdlp_json(&program, &["--no-playlist".to_string()], &url, proxy.as_deref(), cookie.as_deref()).await;
+    }
+    Ok(json)
+}
+
+pub async fn fetch_formats_task(program: PathBuf, url: String, proxy: Option<String>, cookie: Option<PathBuf>) -> Result<Vec<FormatInfo>, String> {
+    let json = run_ytdlp_json(&program, &["--no-playlist".to_string()], &url, proxy.as_deref(), cookie.as_deref()).await?;
+    Ok(parse_formats(&json))
+}
+
+async fn run_ytdlp_json(program: &Path, args: &[String], url: &str, proxy: Option<&str>, cookie: Option<&Path>) -> Result<serde_json::Value, String> {
+    let ytdlp = YtDlpCommand::new(url).program(program).leading_args(std::iter::once("-J".to_string()).chain(args.iter().cloned()))
+        .proxy(proxy.map(str::to_string)).cookie(cookie.map(Path::to_path_buf));
+    let mut cmd = TokioCommand::new(ytdlp.program_path());
+    cmd.args(ytdlp.args());
     #[cfg(windows)] cmd.creation_flags(0x08000000);
 
     let output = cmd.output().await.map_err(|e| format!("Execution failed: {}", e))?;
@@ -32,99 +89,79 @@ pub async fn analyze_url_task(url: String, proxy: Option<String>, cookie: Option
     serde_json::from_str(&json_text).map_err(|e| format!("JSON Parse Error: {}", e))
 }
 
-pub fn download_stream(id: usize, url: String, dir: PathBuf, proxy: Option<String>, cookie: Option<PathBuf>, opts: AdvOptions) -> Subscription<Message> {
+// What one run of a download starts from, put together when the queue starts the item. `kept` are the
+// files of earlier runs, which a resumed download carries on with.
+#[derive(Debug)]
+pub struct DownloadRun { pub command: YtDlpCommand, pub kept: WrittenFiles }
+
+pub fn download_stream(id: usize, run: Arc<DownloadRun>) -> Subscription<Message> {
     iced::subscription::unfold(id, State::Starting, move |state| {
-        let url = url.clone(); let dir = dir.clone(); let proxy = proxy.clone(); let cookie = cookie.clone(); let opts = opts.clone();
+        let run = run.clone();
         async move {
             match state {
                 State::Starting => {
-                    let local_yt = std::env::current_dir().unwrap_or_default().join("yt-dlp.exe");
-                    let cmd_name = if local_yt.exists() { local_yt.to_string_lossy().to_string() } else { "yt-dlp".to_string() };
-                    let mut cmd = TokioCommand::new(cmd_name);
-                    cmd.arg("--newline").arg("--encoding").arg("utf-8").arg("--no-overwrites").arg("--ignore-errors").arg("-P").arg(&dir).arg(&url);
-                    
-                    if let Some(p) = &proxy { cmd.arg("--proxy").arg(p); }
-                    if let Some(c) = &cookie { cmd.arg("--cookies").arg(c); }
-                    if let Ok(local_exe) = std::env::current_dir().map(|d| d.join("ffmpeg.exe")) { if local_exe.exists() { cmd.arg("--ffmpeg-location").arg(local_exe); } }
-
-                    // Apply Filename Template
-                    if let Some(tmpl) = opts.filename_style.to_cmd_arg() {
-                        cmd.arg("-o").arg(tmpl);
-                    }
-
-                    match opts.audio_fmt {
-                        AudioFormat::None => {
-                            let container = opts.container.as_str();
-                            // Video Type Logic (VR/3D)
-                            match opts.video_type {
-                                VideoType::Normal => { cmd.arg("-f").arg("bestvideo+bestaudio/best"); },
-                                VideoType::VR360 => { 
-                                    // Prefer VR formats
-                                    cmd.arg("-S").arg("vr,res,fps,codec"); 
-                                    cmd.arg("-f").arg("bestvideo+bestaudio/best");
-                                },
-                                VideoType::ThreeD => {
-                                    // Prefer 3D formats
-                                    cmd.arg("-S").arg("3d,res,fps,codec"); 
-                                    cmd.arg("-f").arg("bestvideo+bestaudio/best");
-                                }
-                            }
-                            
-                            cmd.arg("--merge-output-format").arg(container);
-                            if opts.embed_subs { 
-                                cmd.arg("--embed-subs"); 
-                                if !opts.sub_langs.is_empty() { cmd.arg("--sub-langs").arg(&opts.sub_langs); }
-                            }
-                            if opts.embed_meta { cmd.arg("--embed-metadata"); }
-                            if opts.embed_thumb { cmd.arg("--embed-thumbnail"); }
-                        },
-                        fmt => {
-                            cmd.arg("-x").arg("--audio-format").arg(fmt.as_str()).arg("--audio-quality").arg("0");
-                            if opts.embed_meta { cmd.arg("--embed-metadata"); }
-                            if opts.embed_thumb { cmd.arg("--embed-thumbnail"); }
-                        }
-                    }
-                    if opts.sponsorblock { cmd.arg("--sponsorblock-remove").arg("all"); }
-                    if !opts.rate_limit.is_empty() { cmd.arg("-r").arg(&opts.rate_limit); }
-                    if !opts.custom_args.is_empty() { for arg in opts.custom_args.split_whitespace() { cmd.arg(arg); } }
+                    let mut log = LogFile::open(id);
+                    let ytdlp = &run.command;
+                    log.write_line(&format!("--- {}", ytdlp.command_line()));
+                    let mut cmd = TokioCommand::new(ytdlp.program_path());
+                    cmd.args(ytdlp.args());
 
                     #[cfg(windows)] cmd.creation_flags(0x08000000); 
-                    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+                    // Own process group so a cancel can take down the ffmpeg children as well
+                    #[cfg(unix)] cmd.process_group(0);
+                    cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true);
                     match cmd.spawn() {
                         Ok(mut child) => {
-                            let stdout = child.stdout.take().unwrap();
-                            (Message::DownloadProgress(id, 0.0, String::new(), String::new()), State::Running(BufReader::new(stdout), child))
+                            // Both pipes are drained as the output comes; a full stderr pipe would otherwise block yt-dlp
+                            let (tx, rx) = mpsc::unbounded_channel();
+                            forward_lines(child.stdout.take().unwrap(), tx.clone(), Output::Stdout);
+                            forward_lines(child.stderr.take().unwrap(), tx, Output::Stderr);
+                            JOBS.lock().unwrap().insert(id, Job { child, files: run.kept.clone() });
+                            let running = Running { rx, tracker: StageTracker::new(ytdlp.download_options()), log, errors: VecDeque::new() };
+                            (Message::DownloadProgress(id, Progress::default()), State::Running(running))
+                        }
+                        Err(e) => {
+                            log.write_line(&format!("Startup Fail: {}", e));
+                            (Message::DownloadFailed(id, DownloadError::new(ErrorKind::Unknown, format!("Startup Fail: {}", e))), State::Finished)
                         }
-                        Err(e) => (Message::DownloadFailed(id, format!("Startup Fail: {}", e)), State::Finished),
                     }
                 }
-                State::Running(mut reader, mut child) => {
-                    let mut line_buf = Vec::new();
-                    use tokio::io::AsyncReadExt; 
-                    match reader.read_until(b'\n', &mut line_buf).await {
-                        Ok(0) => {
+                State::Running(mut run) => {
+                    match run.rx.recv().await {
+                        // Both pipes closed: the process is done or about to be
+                        None => {
+                            // Gone from the table means a cancel already took care of the process
+                            let Some(Job { mut child, .. }) = JOBS.lock().unwrap().remove(&id) else { return (Message::Ignore, State::Finished) };
                             let status = child.wait().await.ok();
-                            if let Some(s) = status { if s.success() { return (Message::DownloadFinished(id), State::Finished); } }
-                            let mut err_msg = String::new();
-                            if let Some(mut stderr) = child.stderr.take() { let _ = stderr.read_to_string(&mut err_msg).await; }
-                            if err_msg.trim().is_empty() { err_msg = "Unknown error (Non-zero exit)".to_string(); }
-                            else { if let Some(last) = err_msg.lines().last() { err_msg = last.to_string(); } }
-                            (Message::DownloadFailed(id, err_msg), State::Finished)
+                            run.log.write_line(&format!("--- exited with {}", status.map(|s| s.to_string()).unwrap_or("unknown status".into())));
+                            match status {
+                                Some(s) if s.success() => (Message::DownloadFinished(id), State::Finished),
+                                _ => (Message::DownloadFailed(id, classify(run.errors.make_contiguous(), status.and_then(|s| s.code()))), State::Finished),
+                            }
+                        }
+                        Some(Output::Stdout(line)) => {
+                            let line = redact_credentials(&line);
+                            // Progress template lines arrive several times a second and would bury everything else
+                            if !line.starts_with(DOWNLOAD_PREFIX) { run.log.write_line(&line); }
+                            if let Some(job) = JOBS.lock().unwrap().get_mut(&id) { job.files.feed(&line); }
+                            if let Some(progress) = run.tracker.feed(&line) {
+                                return (Message::DownloadProgress(id, progress), State::Running(run));
+                            }
+                            let msg = if line.trim().is_empty() { Message::Ignore } else { Message::DownloadLog(id, line) };
+                            (msg, State::Running(run))
                         }
-                        Ok(_) => {
-                            let line = String::from_utf8_lossy(&line_buf);
-                            if let Some(caps) = PROGRESS_RE.captures(&line) {
-                                if let Some(m) = caps.get(1) {
-                                    if let Ok(p) = m.as_str().parse::<f32>() {
-                                        let size = caps.get(2).map(|x| x.as_str()).unwrap_or("?");
-                                        let speed = caps.get(3).map(|x| x.as_str()).unwrap_or("?");
-                                        return (Message::DownloadProgress(id, p, speed.to_string(), size.to_string()), State::Running(reader, child));
-                                    }
-                                }
+                        Some(Output::Stderr(line)) => {
+                            // yt-dlp's proxy errors quote the proxy URL
+                            let line = redact_credentials(&line);
+                            run.log.write_line(&line);
+                            if line.trim().is_empty() { return (Message::Ignore, State::Running(run)); }
+                            if let Some(warning) = line.strip_prefix("WARNING:") {
+                                return (Message::DownloadWarning(id, warning.trim().to_string()), State::Running(run));
                             }
-                            (Message::Ignore, State::Running(reader, child))
+                            if run.errors.len() >= ERROR_TAIL { run.errors.pop_front(); }
+                            run.errors.push_back(line.clone());
+                            (Message::DownloadLog(id, line), State::Running(run))
                         }
-                        Err(e) => (Message::DownloadFailed(id, format!("IO Error: {}", e)), State::Finished),
                     }
                 }
                 State::Finished => { std::future::pending::<()>().await; (Message::Ignore, State::Finished) }
@@ -133,4 +170,166 @@ pub fn download_stream(id: usize, url: String, dir: PathBuf, proxy: Option<Strin
     })
 }
 
-enum State { Starting, Running(BufReader<tokio::process::ChildStdout>, tokio::process::Child), Finished }
\ No newline at end of file
+enum Output { Stdout(String), Stderr(String) }
+
+// Non-warning stderr lines kept for classifying a failure.
+const ERROR_TAIL: usize = 20;
+
+struct Running { rx: mpsc::UnboundedReceiver<Output>, tracker: StageTracker, log: LogFile, errors: VecDeque<String> }
+
+enum State { Starting, Running(Running), Finished }
+
+fn forward_lines<R: AsyncRead + Unpin + Send + 'static>(pipe: R, tx: mpsc::UnboundedSender<Output>, wrap: fn(String) -> Output) {
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(pipe);
+        let mut buf = Vec::new();
+        // Read errors end the stream like EOF does; the exit status then decides how the download ended
+        while matches!(reader.read_until(b'\n', &mut buf).await, Ok(n) if n > 0) {
+            if tx.send(wrap(String::from_utf8_lossy(&buf).trim_end().to_string())).is_err() { break; }
+            buf.clear();
+        }
+    });
+}
+
+// Kills the yt-dlp process tree for `id`, waits for it to exit and either removes the partial files it
+// left behind or hands them back to be kept for a resume or a later `discard_kept_files`.
+pub async fn stop_download_task(id: usize, delete_partials: bool) -> Result<Stopped, String> {
+    let Some(Job { mut child, files }) = JOBS.lock().unwrap().remove(&id) else { return Ok(Stopped::default()) };
+    if let Some(pid) = child.id() {
+        kill_tree(pid, false).await;
+        if tokio::time::timeout(Duration::from_secs(5), child.wait()).await.is_err() {
+            kill_tree(pid, true).await;
+        }
+    }
+    let _ = child.kill().await;
+    if !delete_partials { return Ok(Stopped { removed: 0, kept: files }); }
+    Ok(Stopped { removed: remove_partials(&files).await, kept: WrittenFiles::default() })
+}
+
+// For a download cancelled while paused: it has no process any more, only the files kept at the pause.
+pub async fn discard_kept_files(files: WrittenFiles, delete_partials: bool) -> Result<Stopped, String> {
+    Ok(Stopped { removed: if delete_partials { remove_partials(&files).await } else { 0 }, kept: WrittenFiles::default() })
+}
+
+// Deletes the .part/.ytdl/fragment leftovers of every destination and the destination that was being
+// written when the process died. Finished files stay, e.g. earlier entries of a playlist.
+async fn remove_partials(files: &WrittenFiles) -> usize {
+    let paths = files.destinations.iter().flat_map(|dest| companion_files(dest)).chain(files.in_progress.clone());
+    let mut removed = 0;
+    for path in paths {
+        if tokio::fs::remove_file(&path).await.is_ok() { removed += 1; }
+    }
+    removed
+}
+
+// Stops every running yt-dlp on shutdown. `JOBS` is never dropped, so `kill_on_drop` can't, and in
+// their own process groups the children would otherwise outlive the app. Partial files are kept so
+// the restored queue resumes them.
+pub async fn stop_all_downloads() {
+    let ids: Vec<usize> = JOBS.lock().unwrap().keys().copied().collect();
+    let stops: Vec<_> = ids.into_iter().map(|id| tokio::spawn(stop_download_task(id, false))).collect();
+    for stop in stops { let _ = stop.await; }
+}
+
+async fn kill_tree(pid: u32, force: bool) {
+    #[cfg(windows)] {
+        let _ = force;
+        let mut cmd = TokioCommand::new("taskkill");
+        cmd.args(["/PID", &pid.to_string(), "/T", "/F"]).creation_flags(0x08000000);
+        let _ = cmd.status().await;
+    }
+    #[cfg(unix)] {
+        let sig = if force { "-KILL" } else { "-TERM" };
+        let _ = TokioCommand::new("kill").arg(sig).arg("--").arg(format!("-{}", pid)).status().await;
+    }
+}
+
+// What yt-dlp keeps next to a destination while writing it: its .part/.ytdl companions and any
+// leftover fragments.
+fn companion_files(dest: &Path) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    for ext in [".part", ".ytdl"] {
+        let mut p = dest.as_os_str().to_owned(); p.push(ext); out.push(PathBuf::from(p));
+    }
+    if let (Some(dir), Some(name)) = (dest.parent(), dest.file_name()) {
+        let prefix = format!("{}.part-Frag", name.to_string_lossy());
+        if let Ok(entries) = std::fs::read_dir(if dir.as_os_str().is_empty() { Path::new(".") } else { dir }) {
+            out.extend(entries.flatten().map(|e| e.path()).filter(|p| p.file_name().map(|n| n.to_string_lossy().starts_with(&prefix)).unwrap_or(false)));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs `lines` through a tracker with every destination under `dir`.
+    fn written(dir: &Path, lines: &[&str]) -> WrittenFiles {
+        let mut files = WrittenFiles::default();
+        for line in lines { files.feed(&line.replace("{dir}", &dir.to_string_lossy())); }
+        files
+    }
+
+    fn scratch(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("yt-dlp-gui-test-{}", std::process::id())).join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for f in files { std::fs::write(dir.join(f), b"x").unwrap(); }
+        dir
+    }
+
+    fn left(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir).unwrap().flatten().map(|e| e.file_name().to_string_lossy().to_string()).collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn cancel_mid_playlist_keeps_finished_entries() {
+        let dir = scratch("playlist", &["One [a].mp4", "Two [b].mp4.part", "Two [b].mp4.ytdl", "Two [b].mp4.part-Frag7", "Unrelated.mp4"]);
+        let files = written(&dir, &[
+            "[download] Downloading item 1 of 3",
+            "[download] Destination: {dir}/One [a].mp4",
+            "[gui-progress] downloading|100|200|NA|NA|NA|NA|NA",
+            "[gui-progress] finished|200|200|NA|NA|NA|NA|NA",
+            "[download] Downloading item 2 of 3",
+            "[download] Destination: {dir}/Two [b].mp4",
+            "[gui-progress] downloading|50|200|NA|NA|NA|7|40",
+        ]);
+        assert_eq!(files.in_progress, Some(dir.join("Two [b].mp4")));
+        assert_eq!(remove_partials(&files).await, 3);
+        assert_eq!(left(&dir), ["One [a].mp4", "Unrelated.mp4"]);
+    }
+
+    #[tokio::test]
+    async fn cancel_during_merge_removes_only_the_merge_output() {
+        let dir = scratch("merge", &["V.f137.mp4", "V.f140.m4a", "V.mp4"]);
+        let files = written(&dir, &[
+            "[download] Destination: {dir}/V.f137.mp4",
+            "[gui-progress] finished|100|100|NA|NA|NA|NA|NA",
+            "[download] Destination: {dir}/V.f140.m4a",
+            "[gui-progress] finished|10|10|NA|NA|NA|NA|NA",
+            "[gui-postprocess] started|Merger",
+            "[Merger] Merging formats into \"{dir}/V.mp4\"",
+        ]);
+        assert_eq!(remove_partials(&files).await, 1);
+        assert_eq!(left(&dir), ["V.f137.mp4", "V.f140.m4a"]);
+    }
+
+    #[tokio::test]
+    async fn cancel_after_post_processing_deletes_nothing_finished() {
+        let dir = scratch("done", &["Song.webm", "Song.mp3"]);
+        let files = written(&dir, &[
+            "[download] Destination: {dir}/Song.webm",
+            "[gui-progress] finished|100|100|NA|NA|NA|NA|NA",
+            "[gui-postprocess] started|ExtractAudio",
+            "[ExtractAudio] Destination: {dir}/Song.mp3",
+            "[gui-postprocess] finished|ExtractAudio",
+            "[gui-postprocess] started|Metadata",
+        ]);
+        assert_eq!(files.in_progress, None);
+        assert_eq!(remove_partials(&files).await, 0);
+        assert_eq!(left(&dir), ["Song.mp3", "Song.webm"]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,68 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Lines kept in memory per item for the dashboard panel; the file has the rest.
+pub const MAX_LOG_LINES: usize = 500;
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+// Logs are disposable, so they go in the cache dir rather than next to the settings.
+pub fn logs_dir() -> PathBuf {
+    dirs::cache_dir().unwrap_or_else(|| std::env::current_dir().unwrap_or_default()).join("yt-dlp-gui").join("logs")
+}
+
+pub fn log_path(id: usize) -> PathBuf { logs_dir().join(format!("item-{}.log", id)) }
+
+// Deletes the logs (and rotated `.1` files) of items no longer in the queue. Run at startup: ids are
+// only unique within one queue file, so a new item must never append to a log left by an old one.
+pub fn prune_logs(queued: &[usize]) {
+    let Ok(entries) = std::fs::read_dir(logs_dir()) else { return };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let id = name.strip_prefix("item-").and_then(|n| n.split('.').next()).and_then(|n| n.parse::<usize>().ok());
+        if id.is_some_and(|id| !queued.contains(&id)) { let _ = std::fs::remove_file(entry.path()); }
+    }
+}
+
+pub fn push_line(buf: &mut VecDeque<String>, line: String) {
+    if buf.len() >= MAX_LOG_LINES { buf.pop_front(); }
+    buf.push_back(line);
+}
+
+// Appends one item's yt-dlp output to its log file. Once the file passes MAX_LOG_BYTES it is moved to
+// `.1` (replacing the previous one) and a fresh file is started. Logging is best effort: a log that
+// can't be written never fails the download.
+pub struct LogFile { path: PathBuf, file: Option<File>, size: u64 }
+
+impl LogFile {
+    pub fn open(id: usize) -> Self {
+        let path = log_path(id);
+        if let Some(dir) = path.parent() { let _ = std::fs::create_dir_all(dir); }
+        let file = OpenOptions::new().create(true).append(true).open(&path).ok();
+        let size = file.as_ref().and_then(|f| f.metadata().ok()).map(|m| m.len()).unwrap_or(0);
+        LogFile { path, file, size }
+    }
+
+    pub fn write_line(&mut self, line: &str) {
+        if self.size >= MAX_LOG_BYTES { self.rotate(); }
+        if let Some(f) = self.file.as_mut() {
+            if writeln!(f, "{}", line).is_ok() { self.size += line.len() as u64 + 1; }
+        }
+    }
+
+    fn rotate(&mut self) {
+        self.file = None;
+        let mut old = self.path.as_os_str().to_owned(); old.push(".1");
+        let _ = std::fs::rename(&self.path, PathBuf::from(old));
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path).ok();
+        self.size = 0;
+    }
+}
+
+// Hands the file to whatever the desktop opens it with.
+pub fn open_in_system(path: &Path) -> Result<(), String> {
+    if !path.exists() { return Err("No log file yet".into()); }
+    let program = if cfg!(windows) { "explorer" } else if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    std::process::Command::new(program).arg(path).spawn().map(|_| ()).map_err(|e| format!("Failed to run {}: {}", program, e))
+}
@@ -4,22 +4,46 @@ mod types;
 mod style;
 mod tools;
 mod logic;
+mod config;
+mod playlist;
+mod formats;
+mod quality;
+mod command;
+mod progress;
+mod logs;
+mod errors;
+mod retry;
+mod proxy;
+mod versions;
+mod backups;
 
-use iced::widget::{button, column, container, pick_list, progress_bar, row, scrollable, text, text_input, toggler, Space};
-use iced::{executor, time, alignment, Application, Command, Element, Length, Settings, Subscription, Theme};
+use iced::widget::{button, checkbox, column, container, pick_list, progress_bar, row, scrollable, text, text_input, toggler, Space};
+use iced::{executor, event, time, window, alignment, Application, Command, Element, Length, Settings, Subscription, Theme};
 use iced::theme; 
 
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
 use types::*;
 use style::*;
 use tools::*;
 use logic::*;
+use config::{AppConfig, load_config, save_config, load_queue, save_queue};
+use playlist::{PlaylistRange, PlaylistPicker, format_duration};
+use formats::{FormatBrowser, FormatInfo, format_selector, parse_formats};
+use command::{YtDlpCommand, parse_custom_args};
+use progress::Phase;
+use logs::{log_path, open_in_system, prune_logs, push_line};
+use proxy::{CheckOutcome, ProxyPool, RejectedLine, check_proxies, parse_proxy_list, redact_credentials};
+use backups::KEEP_CHOICES;
+use retry::{RetryClass, RetryPolicy, unix_now, ATTEMPT_CHOICES, BASE_DELAY_CHOICES, MAX_DELAY_CHOICES};
+use quality::{ResolutionCap, FpsCap, VideoCodecPref, AudioCodecPref, DynamicRangePref};
 
 pub fn main() -> iced::Result {
-    YtDownloader::run(Settings::default())
+    // Closing is handled in `update` so running downloads can be stopped first
+    YtDownloader::run(Settings { window: window::Settings { exit_on_close_request: false, ..Default::default() }, ..Settings::default() })
 }
 
 struct YtDownloader {
@@ -33,13 +57,31 @@ struct YtDownloader {
     max_concurrent: usize,
     max_concurrent_input: String,
     active_downloads: usize,
-    proxy_list: Vec<String>,
-    proxy_counter: Arc<AtomicUsize>,
+    proxy_pool: ProxyPool,
+    proxy_rejected: Vec<RejectedLine>,
+    proxy_test_url: String,
+    proxy_check_concurrency: usize,
+    checking_proxies: bool,
+    tool_sources: ToolSources,
+    tool_paths: ToolPaths,
+    tool_channels: ToolChannels,
+    keep_tool_backups: usize,
+    allow_unverified_tools: bool,
+    tool_info: HashMap<Tool, ToolInfo>,
     selected_proxy_proto: ProxyProtocol,
     settings: AdvOptions,
     tool_status: String,
     is_analyzing: bool,
     modal_live_url: Option<String>,
+    delete_partials_on_cancel: bool,
+    retry_policy: RetryPolicy,
+    playlist_items_error: Option<String>,
+    custom_args_error: Option<String>,
+    playlist_picker: Option<PlaylistPicker>,
+    formats: HashMap<String, Vec<FormatInfo>>,
+    format_browser: Option<FormatBrowser>,
+    log_panel: Option<usize>,
+    shutting_down: bool,
 }
 
 impl Application for YtDownloader {
@@ -49,40 +91,49 @@ impl Application for YtDownloader {
     type Flags = ();
 
     fn new(_flags: ()) -> (Self, Command<Message>) {
+        let cfg = load_config();
+        let (queue, next_id) = load_queue();
+        prune_logs(&queue.iter().map(|i| i.id).collect::<Vec<_>>());
+        let tool_info = tool_info_task(cfg.tool_paths.clone());
         (
             YtDownloader {
                 current_tab: AppTab::Dashboard,
                 input_url: String::new(),
-                manual_proxy: String::new(),
-                cookie_path: None,
-                output_dir: std::env::current_dir().unwrap_or_default(),
-                queue: Vec::new(),
-                next_id: 0,
-                max_concurrent: 3,
-                max_concurrent_input: "3".to_string(),
+                manual_proxy: cfg.manual_proxy,
+                cookie_path: cfg.cookie_path,
+                output_dir: cfg.output_dir.unwrap_or_else(|| std::env::current_dir().unwrap_or_default()),
+                queue,
+                next_id,
+                max_concurrent: cfg.max_concurrent,
+                max_concurrent_input: cfg.max_concurrent.to_string(),
                 active_downloads: 0,
-                proxy_list: Vec::new(),
-                proxy_counter: Arc::new(AtomicUsize::new(0)),
-                selected_proxy_proto: ProxyProtocol::Socks5,
-                settings: AdvOptions {
-                    audio_fmt: AudioFormat::None,
-                    container: crate::types::Container::Mp4, 
-                    video_type: VideoType::Normal,
-                    embed_subs: false,
-                    sub_langs: "all".to_string(),
-                    embed_meta: true,
-                    embed_thumb: true,
-                    filename_style: FilenameTemplate::Default,
-                    sponsorblock: false,
-                    playlist_items: String::new(),
-                    rate_limit: String::new(),
-                    custom_args: String::new(),
-                },
+                proxy_pool: ProxyPool::default(),
+                proxy_rejected: Vec::new(),
+                proxy_test_url: cfg.proxy_test_url,
+                proxy_check_concurrency: cfg.proxy_check_concurrency,
+                checking_proxies: false,
+                tool_sources: cfg.tool_sources,
+                tool_paths: cfg.tool_paths,
+                tool_channels: cfg.tool_channels,
+                keep_tool_backups: cfg.keep_tool_backups,
+                allow_unverified_tools: cfg.allow_unverified_tools,
+                tool_info: HashMap::new(),
+                selected_proxy_proto: cfg.proxy_protocol,
                 tool_status: "Initializing...".to_string(),
                 is_analyzing: false,
                 modal_live_url: None,
+                delete_partials_on_cancel: cfg.delete_partials_on_cancel,
+                retry_policy: cfg.retry,
+                playlist_items_error: PlaylistRange::parse(&cfg.settings.playlist_items).err(),
+                custom_args_error: parse_custom_args(&cfg.settings.custom_args).err(),
+                settings: cfg.settings,
+                playlist_picker: None,
+                formats: HashMap::new(),
+                format_browser: None,
+                log_panel: None,
+                shutting_down: false,
             },
-            Command::perform(async {}, |_| Message::CheckForUpdates),
+            Command::batch([Command::perform(tool_info, Message::ToolInfoLoaded), Command::perform(async {}, |_| Message::CheckForUpdates)]),
         )
     }
 
@@ -93,13 +144,12 @@ impl Application for YtDownloader {
         let tick = time::every(std::time::Duration::from_millis(500)).map(|_| Message::Tick);
         let download_subs = self.queue.iter()
             .filter(|item| matches!(item.status, DownloadStatus::Downloading))
-            .map(|item| {
-                download_stream(
-                    item.id, item.url.clone(), self.output_dir.clone(),
-                    item.assigned_proxy.clone(), self.cookie_path.clone(), item.options.clone()
-                )
-            });
-        Subscription::batch(std::iter::once(tick).chain(download_subs))
+            .filter_map(|item| item.run.clone().map(|run| download_stream(item.id, run)));
+        let close = event::listen_with(|e, _| match e {
+            iced::Event::Window(id, window::Event::CloseRequested) => Some(Message::WindowCloseRequested(id)),
+            _ => None,
+        });
+        Subscription::batch([tick, close].into_iter().chain(download_subs))
     }
 
     fn update(&mut self, message: Message) -> Command<Message> {
@@ -107,25 +157,34 @@ impl Application for YtDownloader {
             Message::Ignore => Command::none(),
             Message::TabChanged(tab) => { self.current_tab = tab; Command::none() }
             Message::UrlChanged(s) => { self.input_url = s; Command::none() }
-            Message::ManualProxyChanged(s) => { self.manual_proxy = s; Command::none() }
-            Message::ProxyProtocolChanged(p) => { self.selected_proxy_proto = p; Command::none() }
+            Message::ManualProxyChanged(s) => { self.manual_proxy = s; self.save_config(); Command::none() }
+            Message::ProxyProtocolChanged(p) => { self.selected_proxy_proto = p; self.save_config(); Command::none() }
             
             Message::MaxConcurrentChanged(val) => {
                 if val.chars().all(|c| c.is_numeric()) {
                     self.max_concurrent_input = val.clone();
                     if let Ok(num) = val.parse::<usize>() { self.max_concurrent = num.clamp(1, 50); }
                 } else if val.is_empty() { self.max_concurrent_input = String::new(); }
+                self.save_config();
                 Command::none()
             }
 
             Message::RequestAddUrl => {
                 if !self.input_url.trim().is_empty() && !self.is_analyzing {
+                    let playlist_items = match PlaylistRange::parse(&self.settings.playlist_items) {
+                        Ok(range) => range.map(|r| r.to_arg()),
+                        Err(e) => { self.tool_status = format!("Invalid Playlist Items: {}", e); return Command::none(); }
+                    };
+                    if let Err(e) = parse_custom_args(&self.settings.custom_args) {
+                        self.tool_status = format!("Invalid Custom Arguments: {}", e);
+                        return Command::none();
+                    }
                     self.is_analyzing = true;
                     self.tool_status = "Analyzing Link...".to_string();
                     let url = self.input_url.trim().to_string();
                     let proxy = if !self.manual_proxy.is_empty() { Some(format_proxy(&self.manual_proxy, self.selected_proxy_proto)) } else { None };
                     let cookie = self.cookie_path.clone();
-                    Command::perform(analyze_url_task(url, proxy, cookie), Message::AnalysisFinished)
+                    Command::perform(analyze_url_task(Tool::YtDlp.resolve(&self.tool_paths), url, proxy, cookie, playlist_items), Message::AnalysisFinished)
                 } else { Command::none() }
             }
 
@@ -144,28 +203,27 @@ impl Application for YtDownloader {
                             }
                         }
                         if let Some(entries) = json.get("entries").and_then(|v| v.as_array()) {
-                            let mut added_count = 0;
-                            for entry in entries {
-                                if let Some(id) = entry.get("id").and_then(|s| s.as_str()) {
-                                    let title = entry.get("title").and_then(|s| s.as_str()).unwrap_or("Unknown Title");
-                                    let url = format!("https://www.youtube.com/watch?v={}", id);
-                                    self.queue.push(DownloadItem {
-                                        id: self.next_id, url, title: title.to_string(), status: DownloadStatus::Queued, progress: 0.0, speed: "-".into(), total_size: "-".into(), assigned_proxy: None, options: self.settings.clone(),
-                                    });
-                                    self.next_id += 1; added_count += 1;
-                                }
-                            }
-                            self.tool_status = format!("Added {} videos", added_count);
+                            let range = PlaylistRange::parse(&self.settings.playlist_items).ok().flatten();
+                            let mut picker = PlaylistPicker::from_json(&json, entries, range.as_ref());
+                            picker.range_input = self.settings.playlist_items.clone();
+                            self.tool_status = match picker.skipped {
+                                0 => format!("Found {} videos", picker.entries.len()),
+                                n => format!("Found {} videos ({} entries had no usable URL)", picker.entries.len(), n),
+                            };
+                            self.playlist_picker = Some(picker);
+                            self.input_url.clear();
+                            return Command::none();
                         } else {
                             let title = json.get("title").and_then(|s| s.as_str()).unwrap_or("Video");
                             let url = json.get("webpage_url").and_then(|s| s.as_str()).or(json.get("url").and_then(|s|s.as_str())).unwrap_or(&self.input_url);
-                            self.queue.push(DownloadItem {
-                                id: self.next_id, url: url.to_string(), title: title.to_string(), status: DownloadStatus::Queued, progress: 0.0, speed: "-".into(), total_size: "-".into(), assigned_proxy: None, options: self.settings.clone(),
-                            });
+                            let formats = parse_formats(&json);
+                            if !formats.is_empty() { self.formats.insert(url.to_string(), formats); }
+                            self.queue.push(DownloadItem::new(self.next_id, url.to_string(), title.to_string(), self.settings.clone()));
                             self.next_id += 1;
                         }
                         self.input_url.clear();
                         self.current_tab = AppTab::Dashboard;
+                        self.save_queue();
                     },
                     Err(e) => { self.tool_status = format!("Analysis Failed: {}", e); }
                 }
@@ -176,82 +234,390 @@ impl Application for YtDownloader {
                 if let Some(url) = self.modal_live_url.take() {
                     let mut opts = self.settings.clone();
                     if from_start { opts.custom_args.push_str(" --live-from-start"); }
-                    self.queue.push(DownloadItem {
-                        id: self.next_id, url, title: "Live Stream".into(), status: DownloadStatus::Queued, progress: 0.0, speed: "-".into(), total_size: "-".into(), assigned_proxy: None, options: opts,
-                    });
+                    self.queue.push(DownloadItem::new(self.next_id, url, "Live Stream".into(), opts));
                     self.next_id += 1;
+                    self.save_queue();
+                    self.current_tab = AppTab::Dashboard;
+                }
+                Command::none()
+            }
+            Message::CloseModal => { self.modal_live_url = None; self.playlist_picker = None; self.format_browser = None; Command::none() }
+
+            Message::PlaylistEntryToggled(index, v) => {
+                if let Some(entry) = self.playlist_picker.as_mut().and_then(|p| p.entries.iter_mut().find(|e| e.index == index)) { entry.selected = v; }
+                Command::none()
+            }
+            Message::PlaylistSelectAll => { if let Some(p) = self.playlist_picker.as_mut() { p.select_visible(true); } Command::none() }
+            Message::PlaylistSelectNone => { if let Some(p) = self.playlist_picker.as_mut() { p.select_visible(false); } Command::none() }
+            Message::PlaylistFilterChanged(v) => { if let Some(p) = self.playlist_picker.as_mut() { p.filter = v; } Command::none() }
+            Message::PlaylistRangeChanged(v) => { if let Some(p) = self.playlist_picker.as_mut() { p.range_input = v; } Command::none() }
+            Message::PlaylistApplyRange => { if let Some(p) = self.playlist_picker.as_mut() { p.apply_range(); } Command::none() }
+            Message::PlaylistConfirm => {
+                if let Some(picker) = self.playlist_picker.take() {
+                    // The range has been applied to the playlist itself, the items are single videos
+                    let opts = AdvOptions { playlist_items: String::new(), ..self.settings.clone() };
+                    let mut added_count = 0;
+                    for entry in picker.entries.into_iter().filter(|e| e.selected) {
+                        self.queue.push(DownloadItem::new(self.next_id, entry.url, entry.title, opts.clone()));
+                        self.next_id += 1; added_count += 1;
+                    }
+                    self.tool_status = format!("Added {} videos", added_count);
                     self.current_tab = AppTab::Dashboard;
+                    self.save_queue();
                 }
                 Command::none()
             }
-            Message::CloseModal => { self.modal_live_url = None; Command::none() }
-
-            Message::AudioFmtChanged(v) => { self.settings.audio_fmt = v; Command::none() }
-            Message::ContainerChanged(v) => { self.settings.container = v; Command::none() }
-            Message::VideoTypeChanged(v) => { self.settings.video_type = v; Command::none() }
-            Message::FilenameStyleChanged(v) => { self.settings.filename_style = v; Command::none() }
-            Message::ToggleEmbedSubs(v) => { self.settings.embed_subs = v; Command::none() }
-            Message::SubLangsChanged(v) => { self.settings.sub_langs = v; Command::none() }
-            Message::ToggleEmbedMeta(v) => { self.settings.embed_meta = v; Command::none() }
-            Message::ToggleEmbedThumb(v) => { self.settings.embed_thumb = v; Command::none() }
-            Message::ToggleSponsorBlock(v) => { self.settings.sponsorblock = v; Command::none() }
-            Message::PlaylistItemsChanged(v) => { self.settings.playlist_items = v; Command::none() }
-            Message::RateLimitChanged(v) => { self.settings.rate_limit = v; Command::none() }
-            Message::CustomArgsChanged(v) => { self.settings.custom_args = v; Command::none() }
+
+            Message::CopyCommand(id) => {
+                let Some(item) = self.queue.iter().find(|x| x.id == id) else { return Command::none() };
+                let line = self.download_command(item).command_line();
+                self.tool_status = "Command line copied".to_string();
+                iced::clipboard::write(line)
+            }
+            Message::ToggleLogPanel(id) => { self.log_panel = if self.log_panel == Some(id) { None } else { Some(id) }; Command::none() }
+            Message::CopyLog(id) => {
+                let Some(item) = self.queue.iter().find(|x| x.id == id) else { return Command::none() };
+                // The file has the whole history; the panel only keeps the tail
+                let log = std::fs::read_to_string(log_path(id)).unwrap_or_else(|_| item.log.iter().cloned().collect::<Vec<_>>().join("\n"));
+                // Logs written before credentials were left out may still have some
+                let log = redact_credentials(&log);
+                self.tool_status = "Log copied".to_string();
+                iced::clipboard::write(log)
+            }
+            Message::OpenLogFile(id) => {
+                if let Err(e) = open_in_system(&log_path(id)) { self.tool_status = format!("Can't open log: {}", e); }
+                Command::none()
+            }
+            Message::OpenFormatBrowser(id) => {
+                let Some(item) = self.queue.iter().find(|x| x.id == id) else { return Command::none() };
+                let cached = self.formats.contains_key(&item.url);
+                self.format_browser = Some(FormatBrowser {
+                    item_id: id, url: item.url.clone(), title: item.title.clone(), loading: !cached, error: None,
+                    video: item.video_format.clone(),
+                    audio: item.audio_format.clone().filter(|a| a != "bestaudio"),
+                });
+                if cached { return Command::none(); }
+                let url = item.url.clone();
+                let proxy = if !self.manual_proxy.is_empty() { Some(format_proxy(&self.manual_proxy, self.selected_proxy_proto)) } else { item.assigned_proxy.clone() };
+                Command::perform(fetch_formats_task(Tool::YtDlp.resolve(&self.tool_paths), url.clone(), proxy, self.cookie_path.clone()), move |r| Message::FormatsLoaded(url, r))
+            }
+            Message::FormatsLoaded(url, res) => {
+                let err = match res { Ok(list) => { self.formats.insert(url.clone(), list); None } Err(e) => Some(e) };
+                if let Some(b) = self.format_browser.as_mut().filter(|b| b.url == url) { b.loading = false; b.error = err; }
+                Command::none()
+            }
+            Message::PickVideoFormat(v) => {
+                if let Some(b) = self.format_browser.as_mut() {
+                    // A combined format already carries its own audio
+                    let muxed = v.as_ref().and_then(|id| self.formats.get(&b.url)?.iter().find(|f| &f.id == id)).is_some_and(|f| f.has_audio());
+                    if muxed { b.audio = None; }
+                    b.video = v;
+                }
+                Command::none()
+            }
+            Message::PickAudioFormat(a) => { if let Some(b) = self.format_browser.as_mut() { b.audio = a; } Command::none() }
+            Message::ApplyFormats => {
+                if let Some(b) = self.format_browser.take() {
+                    let video_only = b.video.as_ref().and_then(|id| self.formats.get(&b.url)?.iter().find(|f| &f.id == id)).is_some_and(|f| !f.has_audio());
+                    if let Some(item) = self.queue.iter_mut().find(|x| x.id == b.item_id) {
+                        item.audio_format = if video_only && b.audio.is_none() { Some("bestaudio".into()) } else { b.audio };
+                        item.video_format = b.video;
+                    }
+                    self.save_queue();
+                }
+                Command::none()
+            }
+
+            Message::AudioFmtChanged(v) => { self.settings.audio_fmt = v; self.save_config(); Command::none() }
+            Message::ContainerChanged(v) => { self.settings.container = v; self.save_config(); Command::none() }
+            Message::VideoTypeChanged(v) => { self.settings.video_type = v; self.save_config(); Command::none() }
+            Message::FilenameStyleChanged(v) => { self.settings.filename_style = v; self.save_config(); Command::none() }
+            Message::MaxResolutionChanged(v) => { self.settings.quality.max_resolution = v; self.save_config(); Command::none() }
+            Message::MaxFpsChanged(v) => { self.settings.quality.max_fps = v; self.save_config(); Command::none() }
+            Message::VideoCodecChanged(v) => { self.settings.quality.video_codec = v; self.save_config(); Command::none() }
+            Message::AudioCodecChanged(v) => { self.settings.quality.audio_codec = v; self.save_config(); Command::none() }
+            Message::DynamicRangeChanged(v) => { self.settings.quality.dynamic_range = v; self.save_config(); Command::none() }
+            Message::MaxFilesizeChanged(v) => { self.settings.quality.max_filesize = v; self.save_config(); Command::none() }
+            Message::ToggleEmbedSubs(v) => { self.settings.embed_subs = v; self.save_config(); Command::none() }
+            Message::SubLangsChanged(v) => { self.settings.sub_langs = v; self.save_config(); Command::none() }
+            Message::ToggleEmbedMeta(v) => { self.settings.embed_meta = v; self.save_config(); Command::none() }
+            Message::ToggleEmbedThumb(v) => { self.settings.embed_thumb = v; self.save_config(); Command::none() }
+            Message::ToggleSponsorBlock(v) => { self.settings.sponsorblock = v; self.save_config(); Command::none() }
+            Message::PlaylistItemsChanged(v) => {
+                self.playlist_items_error = PlaylistRange::parse(&v).err();
+                self.settings.playlist_items = v;
+                self.save_config();
+                Command::none()
+            }
+            Message::RateLimitChanged(v) => { self.settings.rate_limit = v; self.save_config(); Command::none() }
+            Message::CustomArgsChanged(v) => {
+                self.custom_args_error = parse_custom_args(&v).err();
+                self.settings.custom_args = v;
+                self.save_config();
+                Command::none()
+            }
             
             Message::RetryDownload(id) => {
-                if let Some(item) = self.queue.iter_mut().find(|x| x.id == id) { item.status = DownloadStatus::Queued; item.progress = 0.0; }
+                if let Some(item) = self.queue.iter_mut().find(|x| x.id == id) { item.status = DownloadStatus::Queued; item.progress = 0.0; item.attempts = 0; item.next_retry = None; }
+                self.save_queue();
                 Command::none()
             }
             Message::CancelDownload(id) => {
+                let mut stop = Command::none();
                 if let Some(item) = self.queue.iter_mut().find(|x| x.id == id) {
-                    if matches!(item.status, DownloadStatus::Downloading) { self.active_downloads = self.active_downloads.saturating_sub(1); }
+                    if matches!(item.status, DownloadStatus::Downloading) {
+                        self.active_downloads = self.active_downloads.saturating_sub(1);
+                        item.speed = String::from("Stopping...");
+                        item.stopping = true;
+                        stop = Command::perform(stop_download_task(id, self.delete_partials_on_cancel), move |r| Message::DownloadStopped(id, r));
+                    } else if matches!(item.status, DownloadStatus::Paused) && !item.stopping {
+                        // Still pausing: the files come with `DownloadStopped` and are dealt with there
+                        stop = Command::perform(discard_kept_files(std::mem::take(&mut item.kept_files), self.delete_partials_on_cancel), move |r| Message::DownloadStopped(id, r));
+                    }
                     item.status = DownloadStatus::Cancelled;
+                    item.next_retry = None;
+                }
+                self.save_queue();
+                stop
+            }
+            Message::PauseDownload(id) => {
+                let mut stop = Command::none();
+                if let Some(item) = self.queue.iter_mut().find(|x| x.id == id) {
+                    match item.status {
+                        DownloadStatus::Downloading => {
+                            // Keep the partial files so yt-dlp can continue from the .part file on resume
+                            self.active_downloads = self.active_downloads.saturating_sub(1);
+                            item.speed = String::from("Pausing...");
+                            item.stopping = true;
+                            stop = Command::perform(stop_download_task(id, false), move |r| Message::DownloadStopped(id, r));
+                        }
+                        DownloadStatus::Queued => item.speed = String::from("Paused"),
+                        _ => return Command::none(),
+                    }
+                    item.status = DownloadStatus::Paused;
+                }
+                self.save_queue();
+                stop
+            }
+            Message::ResumeDownload(id) => {
+                // Not before the old process is gone and its files are back on the item
+                if let Some(item) = self.queue.iter_mut().find(|x| x.id == id && matches!(x.status, DownloadStatus::Paused) && !x.stopping) {
+                    item.status = DownloadStatus::Queued;
+                    item.speed = "-".into();
+                }
+                self.save_queue();
+                Command::none()
+            }
+            Message::DownloadStopped(id, res) => {
+                let Some(item) = self.queue.iter_mut().find(|x| x.id == id) else { return Command::none() };
+                item.stopping = false;
+                let (removed, kept) = match res { Ok(s) => (Ok(s.removed), s.kept), Err(e) => (Err(e), WrittenFiles::default()) };
+                match item.status {
+                    DownloadStatus::Paused => {
+                        item.kept_files = kept;
+                        item.speed = match removed { Ok(_) => String::from("Paused"), Err(e) => format!("Paused, stop failed: {}", e) };
+                        self.save_queue();
+                        return Command::none();
+                    }
+                    // Cancelled while the pause was still stopping the process
+                    DownloadStatus::Cancelled if !kept.is_empty() && self.delete_partials_on_cancel => {
+                        return Command::perform(discard_kept_files(kept, true), move |r| Message::DownloadStopped(id, r));
+                    }
+                    _ => {}
                 }
+                item.speed = match removed {
+                    Ok(0) => String::from("Stopped"),
+                    Ok(n) => format!("Stopped, removed {} partial file(s)", n),
+                    Err(e) => format!("Stopped, cleanup failed: {}", e),
+                };
                 Command::none()
             }
+            Message::WindowCloseRequested(id) => {
+                self.shutting_down = true;
+                self.tool_status = "Stopping downloads...".to_string();
+                Command::perform(stop_all_downloads(), move |_| Message::CloseWindow(id))
+            }
+            Message::CloseWindow(id) => window::close(id),
             Message::Tick => {
+                if self.shutting_down { return Command::none(); }
+                let now = unix_now();
+                let mut requeued = false;
+                for item in self.queue.iter_mut().filter(|x| matches!(x.status, DownloadStatus::Failed(_)) && x.next_retry.is_some_and(|t| t <= now)) {
+                    item.status = DownloadStatus::Queued;
+                    item.next_retry = None;
+                    item.progress = 0.0;
+                    requeued = true;
+                }
+                if requeued { self.save_queue(); }
                 if self.active_downloads < self.max_concurrent {
-                    if let Some(item) = self.queue.iter_mut().find(|x| matches!(x.status, DownloadStatus::Queued)) {
+                    if let Some(idx) = self.queue.iter().position(|x| matches!(x.status, DownloadStatus::Queued)) {
+                        let item = &mut self.queue[idx];
                         item.status = DownloadStatus::Downloading;
+                        item.log.clear();
+                        item.notices.clear();
                         self.active_downloads += 1;
-                        if !self.manual_proxy.is_empty() { item.assigned_proxy = Some(format_proxy(&self.manual_proxy, self.selected_proxy_proto)); } 
-                        else if !self.proxy_list.is_empty() {
-                            let count = self.proxy_counter.fetch_add(1, Ordering::SeqCst);
-                            let p = self.proxy_list[count % self.proxy_list.len()].clone();
+                        if !self.manual_proxy.is_empty() { item.assigned_proxy = Some(format_proxy(&self.manual_proxy, self.selected_proxy_proto)); item.proxy_entry = None; } 
+                        // Steer clear of the proxy the item last ran through; after a failure that's the likely culprit
+                        else if let Some(p) = self.proxy_pool.pick(item.proxy_entry.as_deref()) {
                             item.assigned_proxy = Some(format_proxy(&p, self.selected_proxy_proto));
+                            item.proxy_entry = Some(p);
                         }
+                        item.started = Some(Instant::now());
+                        // Resolved once here rather than in `subscription`, which runs after every message
+                        let run = DownloadRun { command: self.download_command(&self.queue[idx]), kept: self.queue[idx].kept_files.clone() };
+                        self.queue[idx].run = Some(Arc::new(run));
+                        self.save_queue();
+                    }
+                }
+                Command::none()
+            }
+            Message::DownloadProgress(id, p) => {
+                if let Some(item) = self.queue.iter_mut().find(|x| x.id == id) {
+                    if let Some(overall) = p.overall { item.progress = overall; }
+                    if p.phase == Phase::Downloading {
+                        if let (Some(start), Some(entry)) = (item.started.take(), &item.proxy_entry) { self.proxy_pool.record_latency(entry, start.elapsed()); }
+                        item.speed = p.speed_label();
+                        if p.total.is_some() { item.total_size = p.size_label(); }
                     }
+                    item.live = p;
                 }
                 Command::none()
             }
-            Message::DownloadProgress(id, prog, spd, sz) => {
-                if let Some(item) = self.queue.iter_mut().find(|x| x.id == id) { item.progress = prog; if !spd.is_empty() { item.speed = spd; } if !sz.is_empty() { item.total_size = sz; } }
+            Message::DownloadLog(id, line) => {
+                if let Some(item) = self.queue.iter_mut().find(|x| x.id == id) { push_line(&mut item.log, line); }
+                Command::none()
+            }
+            Message::DownloadWarning(id, warning) => {
+                if let Some(item) = self.queue.iter_mut().find(|x| x.id == id) {
+                    push_line(&mut item.log, format!("WARNING: {}", warning));
+                    if !item.notices.contains(&warning) && item.notices.len() < MAX_NOTICES { item.notices.push(warning); }
+                }
                 Command::none()
             }
             Message::DownloadFinished(id) => {
-                if let Some(item) = self.queue.iter_mut().find(|x| x.id == id) { item.status = DownloadStatus::Finished; item.progress = 100.0; item.speed = String::from("Done"); self.active_downloads = self.active_downloads.saturating_sub(1); }
+                if let Some(item) = self.queue.iter_mut().find(|x| x.id == id) {
+                    if let Some(entry) = &item.proxy_entry { self.proxy_pool.record_success(entry); }
+                    item.status = DownloadStatus::Finished; item.progress = 100.0; item.speed = String::from("Done"); self.active_downloads = self.active_downloads.saturating_sub(1);
+                    item.kept_files = WrittenFiles::default();
+                }
+                self.save_queue();
                 Command::none()
             }
             Message::DownloadFailed(id, err) => {
-                if let Some(item) = self.queue.iter_mut().find(|x| x.id == id) { item.status = DownloadStatus::Failed(err); item.speed = String::from("Failed"); self.active_downloads = self.active_downloads.saturating_sub(1); }
+                if let Some(item) = self.queue.iter_mut().find(|x| x.id == id) {
+                    if let Some(entry) = &item.proxy_entry { self.proxy_pool.record_failure(entry, err.kind); }
+                    item.attempts += 1;
+                    item.next_retry = self.retry_policy.next_retry(err.kind, item.attempts);
+                    item.status = DownloadStatus::Failed(err);
+                    item.speed = String::from("Failed");
+                    self.active_downloads = self.active_downloads.saturating_sub(1);
+                }
+                self.save_queue();
                 Command::none()
             }
             
             Message::PickCookieFile => { Command::perform(async { rfd::AsyncFileDialog::new().pick_file().await.map(|f| f.path().to_path_buf()) }, Message::CookieFilePicked) }
-            Message::CookieFilePicked(p) => { self.cookie_path = p; Command::none() }
+            Message::ToggleDeletePartials(v) => { self.delete_partials_on_cancel = v; self.save_config(); Command::none() }
+            Message::ToggleRetry(v) => { self.retry_policy.enabled = v; self.save_config(); Command::none() }
+            Message::RetryAttemptsChanged(v) => { self.retry_policy.max_attempts = v; self.save_config(); Command::none() }
+            Message::RetryBaseDelayChanged(v) => { self.retry_policy.base_delay_secs = v; self.save_config(); Command::none() }
+            Message::RetryMaxDelayChanged(v) => { self.retry_policy.max_delay_secs = v; self.save_config(); Command::none() }
+            Message::RetryClassToggled(class, on) => {
+                self.retry_policy.classes.retain(|c| *c != class);
+                if on { self.retry_policy.classes.push(class); }
+                self.save_config();
+                Command::none()
+            }
+            Message::PickToolPath(tool) => { Command::perform(async { rfd::AsyncFileDialog::new().pick_file().await.map(|f| f.path().to_path_buf()) }, move |p| Message::ToolPathPicked(tool, p)) }
+            Message::ToolPathPicked(tool, p) => { if p.is_some() { self.tool_paths.set(tool, p); self.save_config(); } self.load_tool_info() }
+            Message::ClearToolPath(tool) => { self.tool_paths.set(tool, None); self.save_config(); self.load_tool_info() }
+            Message::ToolChannelChanged(tool, channel) => { *self.tool_channels.get_mut(tool) = ToolChannel { channel, skip_next_update: false, ..self.tool_channels.get(tool).clone() }; self.save_config(); Command::none() }
+            Message::PinnedVersionChanged(tool, v) => { self.tool_channels.get_mut(tool).pinned_version = v; self.save_config(); Command::none() }
+            Message::KeepBackupsChanged(n) => { self.keep_tool_backups = n; self.save_config(); Command::none() }
+            Message::ToggleAllowUnverified(v) => { self.allow_unverified_tools = v; self.save_config(); Command::none() }
+            Message::RollbackTool(tool, backup) => {
+                self.tool_status = format!("Rolling back {}...", tool.label());
+                Command::perform(rollback_task(tool, backup, self.keep_tool_backups), move |r| Message::ToolRolledBack(tool, r))
+            }
+            Message::ToolRolledBack(tool, res) => {
+                match res {
+                    // Pin it, or the next update check would put the release that broke things straight back.
+                    // Nightlies, snapshots and unlabelled backups can't be downloaded again, so those only hold off one check.
+                    Ok(version) if self.tool_sources.can_pin(tool, &version) => {
+                        *self.tool_channels.get_mut(tool) = ToolChannel { channel: UpdateChannel::Pinned, pinned_version: version.clone(), skip_next_update: false };
+                        self.save_config();
+                        self.tool_status = format!("{} rolled back to {} and pinned", tool.label(), version);
+                    }
+                    Ok(version) => {
+                        self.tool_channels.get_mut(tool).skip_next_update = true;
+                        self.save_config();
+                        self.tool_status = format!("{} rolled back to {}; the next update check will leave it", tool.label(), version);
+                    }
+                    Err(e) => self.tool_status = format!("Rollback failed: {}", e),
+                }
+                self.load_tool_info()
+            }
+            Message::ToolInfoLoaded(info) => { self.tool_info = info.into_iter().collect(); Command::none() }
+            Message::CookieFilePicked(p) => { self.cookie_path = p; self.save_config(); Command::none() }
             Message::PickOutputDir => { Command::perform(async { rfd::AsyncFileDialog::new().pick_folder().await.map(|f| f.path().to_path_buf()) }, Message::OutputDirPicked) }
-            Message::OutputDirPicked(p) => { if let Some(path) = p { self.output_dir = path; } Command::none() }
+            Message::OutputDirPicked(p) => { if let Some(path) = p { self.output_dir = path; self.save_config(); } Command::none() }
             Message::PickProxyList => { Command::perform(async { let file = rfd::AsyncFileDialog::new().pick_file().await; if let Some(f) = file { tokio::fs::read_to_string(f.path().to_path_buf()).await.ok() } else { None } }, Message::ProxyListLoaded) }
-            Message::ProxyListLoaded(c) => { if let Some(text) = c { self.proxy_list = text.lines().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect(); } Command::none() }
-            Message::CheckForUpdates => { self.tool_status = "Checking updates...".to_string(); Command::perform(auto_update_task(), Message::ToolInstalled) }
+            Message::ProxyListLoaded(c) => {
+                if let Some(text) = c {
+                    let (accepted, rejected) = parse_proxy_list(&text);
+                    self.tool_status = match rejected.len() {
+                        0 => format!("Loaded {} proxies", accepted.len()),
+                        n => format!("Loaded {} proxies, rejected {} line(s)", accepted.len(), n),
+                    };
+                    self.proxy_pool = ProxyPool::new(accepted);
+                    self.proxy_rejected = rejected;
+                }
+                Command::none()
+            }
+            Message::ResetProxyHealth => { self.proxy_pool.reset_health(); Command::none() }
+            Message::ProxyTestUrlChanged(v) => { self.proxy_test_url = v; self.save_config(); Command::none() }
+            Message::CheckProxies => {
+                if self.checking_proxies || self.proxy_pool.len() == 0 { return Command::none(); }
+                self.checking_proxies = true;
+                self.tool_status = format!("Checking {} proxies...", self.proxy_pool.len());
+                let list = self.proxy_pool.entries.iter().map(|e| (e.raw.clone(), format_proxy(&e.raw, self.selected_proxy_proto))).collect();
+                Command::perform(check_proxies(list, self.proxy_test_url.trim().to_string(), self.proxy_check_concurrency), Message::ProxiesChecked)
+            }
+            Message::ProxiesChecked(results) => {
+                self.checking_proxies = false;
+                let alive = results.iter().filter(|(_, r)| matches!(r, CheckOutcome::Alive { .. })).count();
+                let unchecked = results.iter().filter(|(_, r)| matches!(r, CheckOutcome::Unchecked(_))).count();
+                self.tool_status = match unchecked {
+                    0 => format!("Proxy check: {} of {} alive", alive, results.len()),
+                    n => format!("Proxy check: {} of {} alive, {} not checkable", alive, results.len() - n, n),
+                };
+                self.proxy_pool.apply_checks(results);
+                Command::none()
+            }
+            Message::DropDeadProxies => {
+                let removed = self.proxy_pool.drop_dead();
+                self.tool_status = format!("Dropped {} dead proxies", removed);
+                Command::none()
+            }
+            Message::CheckForUpdates => {
+                self.tool_status = "Checking updates...".to_string();
+                let channels = self.tool_channels.clone();
+                if channels.ytdlp.skip_next_update || channels.ffmpeg.skip_next_update {
+                    self.tool_channels.ytdlp.skip_next_update = false;
+                    self.tool_channels.ffmpeg.skip_next_update = false;
+                    self.save_config();
+                }
+                Command::perform(auto_update_task(self.tool_sources.clone(), self.tool_paths.clone(), channels, self.keep_tool_backups, self.allow_unverified_tools), Message::ToolInstalled)
+            }
             // Removed unused install handlers logic to avoid dead code logic
-            Message::ToolInstalled(res) => { match res { Ok(m) => self.tool_status = m, Err(e) => self.tool_status = format!("Error: {}", e) } Command::none() }
+            Message::ToolInstalled(res) => { match res { Ok(m) => self.tool_status = m, Err(e) => self.tool_status = format!("Error: {}", e) } self.load_tool_info() }
         }
     }
 
     fn view(&self) -> Element<'_, Message> {
         if self.modal_live_url.is_some() { return self.view_live_modal(); }
+        if let Some(picker) = &self.playlist_picker { return self.view_playlist_modal(picker); }
+        if let Some(browser) = &self.format_browser { return self.view_format_modal(browser); }
 
         let tab_btn = |label, tab, active_tab| {
             let style = if tab == active_tab { theme::Button::Primary } else { theme::Button::Secondary };
@@ -275,6 +641,46 @@ impl Application for YtDownloader {
 }
 
 impl YtDownloader {
+    fn save_config(&mut self) {
+        let cfg = AppConfig {
+            settings: self.settings.clone(),
+            output_dir: Some(self.output_dir.clone()),
+            cookie_path: self.cookie_path.clone(),
+            proxy_protocol: self.selected_proxy_proto,
+            manual_proxy: self.manual_proxy.clone(),
+            max_concurrent: self.max_concurrent,
+            delete_partials_on_cancel: self.delete_partials_on_cancel,
+            retry: self.retry_policy.clone(),
+            proxy_test_url: self.proxy_test_url.clone(),
+            proxy_check_concurrency: self.proxy_check_concurrency,
+            tool_sources: self.tool_sources.clone(),
+            tool_paths: self.tool_paths.clone(),
+            tool_channels: self.tool_channels.clone(),
+            keep_tool_backups: self.keep_tool_backups,
+            allow_unverified_tools: self.allow_unverified_tools,
+            ..AppConfig::default()
+        };
+        if let Err(e) = save_config(&cfg) { self.tool_status = format!("Failed to save settings: {}", e); }
+    }
+
+    fn load_tool_info(&self) -> Command<Message> { Command::perform(tool_info_task(self.tool_paths.clone()), Message::ToolInfoLoaded) }
+
+    fn download_command(&self, item: &DownloadItem) -> YtDlpCommand {
+        YtDlpCommand::new(item.url.clone())
+            .output_dir(self.output_dir.clone())
+            .proxy(item.assigned_proxy.clone())
+            .cookie(self.cookie_path.clone())
+            .program(Tool::YtDlp.resolve(&self.tool_paths))
+            // yt-dlp finds an ffmpeg on PATH by itself; only other locations need passing on
+            .ffmpeg(Tool::Ffmpeg.locate(&self.tool_paths).filter(|(_, origin)| *origin != ToolOrigin::Path).map(|(p, _)| p))
+            .format(format_selector(item.video_format.as_deref(), item.audio_format.as_deref()))
+            .options(&item.options)
+    }
+
+    fn save_queue(&mut self) {
+        if let Err(e) = save_queue(&self.queue, self.next_id) { self.tool_status = format!("Failed to save queue: {}", e); }
+    }
+
     fn view_live_modal(&self) -> Element<'_, Message> {
         let content = column![
             text("🔴 Livestream Detected!").size(24).font(iced::font::Font::with_name("bold")),
@@ -292,6 +698,101 @@ impl YtDownloader {
         container(content).width(Length::Fill).height(Length::Fill).center_x().center_y().style(theme::Container::Custom(Box::new(DarkBackgroundStyle))).into()
     }
 
+    fn view_playlist_modal<'a>(&'a self, picker: &'a PlaylistPicker) -> Element<'a, Message> {
+        let dim = |s: String| text(s).size(12).style(theme::Text::Color(hex_color("#bac2de")));
+        let rows: Element<Message> = column(picker.entries.iter().filter(|e| picker.matches_filter(e)).map(|entry| {
+            let index = entry.index;
+            row![
+                checkbox("", entry.selected).on_toggle(move |v| Message::PlaylistEntryToggled(index, v)),
+                dim(format!("#{}", entry.index)).width(45),
+                text(&entry.title).size(14).width(Length::Fill),
+                dim(entry.uploader.clone().unwrap_or_default()).width(160),
+                dim(entry.duration.map(format_duration).unwrap_or("-".into())).width(60),
+            ].spacing(10).align_items(alignment::Alignment::Center).into()
+        }).collect::<Vec<_>>()).spacing(6).into();
+
+        let selected = picker.selected_count();
+        let content = column![
+            text(format!("📃 {}", picker.title)).size(22).font(iced::font::Font::with_name("bold")),
+            dim(format!("{} of {} videos selected", selected, picker.entries.len())),
+            row![
+                text_input("Filter by title or uploader...", &picker.filter).on_input(Message::PlaylistFilterChanged).padding(8),
+                button("Select All").on_press(Message::PlaylistSelectAll).style(theme::Button::Secondary),
+                button("Select None").on_press(Message::PlaylistSelectNone).style(theme::Button::Secondary),
+            ].spacing(10).align_items(alignment::Alignment::Center),
+            row![
+                text("Range:"),
+                text_input("1,2,5-10", &picker.range_input).on_input(Message::PlaylistRangeChanged).on_submit(Message::PlaylistApplyRange).padding(8).width(200),
+                button("Apply").on_press(Message::PlaylistApplyRange).style(theme::Button::Secondary),
+                text(picker.range_error.as_deref().unwrap_or("")).size(12).style(theme::Text::Color(hex_color("#f38ba8"))),
+            ].spacing(10).align_items(alignment::Alignment::Center),
+            container(scrollable(rows)).style(theme::Container::Custom(Box::new(DarkCardStyle))).padding(12).height(Length::Fill),
+            row![
+                Space::with_width(Length::Fill),
+                button("Cancel").on_press(Message::CloseModal).style(theme::Button::Destructive),
+                button(text(format!("Add {} videos", selected))).on_press_maybe(if selected > 0 { Some(Message::PlaylistConfirm) } else { None }).style(theme::Button::Primary),
+            ].spacing(10),
+        ].spacing(12).padding(30);
+
+        container(content).width(Length::Fill).height(Length::Fill).style(theme::Container::Custom(Box::new(DarkBackgroundStyle))).into()
+    }
+
+    fn view_format_modal<'a>(&'a self, browser: &'a FormatBrowser) -> Element<'a, Message> {
+        let dim = |s: String| text(s).size(12).style(theme::Text::Color(hex_color("#bac2de")));
+        let header = || row![
+            dim("ID".into()).width(60), dim("Ext".into()).width(50), dim("Resolution".into()).width(100), dim("FPS".into()).width(40),
+            dim("Codecs".into()).width(Length::Fill), dim("Bitrate".into()).width(70), dim("Size".into()).width(80), dim("HDR".into()).width(40),
+        ].spacing(10).padding([0, 8]);
+        let format_row = |f: &FormatInfo, selected: bool, msg: Message| -> Element<'a, Message> {
+            let cells = row![
+                text(&f.id).size(12).width(60), text(&f.ext).size(12).width(50), text(f.resolution()).size(12).width(100),
+                text(f.fps.map(|x| format!("{:.0}", x)).unwrap_or("-".into())).size(12).width(40),
+                text(f.codecs()).size(12).width(Length::Fill), text(f.bitrate()).size(12).width(70), text(f.size()).size(12).width(80),
+                text(if f.is_hdr() { "HDR" } else { "" }).size(12).width(40),
+            ].spacing(10);
+            button(cells).on_press(msg).width(Length::Fill).style(if selected { theme::Button::Primary } else { theme::Button::Text }).into()
+        };
+        let auto_row = |label: &'a str, selected: bool, msg: Message| -> Element<'a, Message> {
+            button(text(label).size(12)).on_press(msg).width(Length::Fill).style(if selected { theme::Button::Primary } else { theme::Button::Text }).into()
+        };
+
+        let body: Element<Message> = match (self.formats.get(&browser.url), &browser.error) {
+            (_, Some(e)) => text(format!("Could not load formats: {}", e)).style(theme::Text::Color(hex_color("#f38ba8"))).into(),
+            (None, _) => text("Loading formats...").into(),
+            (Some(list), None) => {
+                let mut videos: Vec<&FormatInfo> = list.iter().filter(|f| f.has_video()).collect();
+                videos.sort_by(|a, b| b.height.cmp(&a.height).then(b.tbr.partial_cmp(&a.tbr).unwrap_or(std::cmp::Ordering::Equal)));
+                let mut audios: Vec<&FormatInfo> = list.iter().filter(|f| !f.has_video()).collect();
+                audios.sort_by(|a, b| b.tbr.partial_cmp(&a.tbr).unwrap_or(std::cmp::Ordering::Equal));
+
+                let mut video_col = vec![header().into(), auto_row("Best video (automatic)", browser.video.is_none(), Message::PickVideoFormat(None))];
+                video_col.extend(videos.into_iter().map(|f| format_row(f, browser.video.as_ref() == Some(&f.id), Message::PickVideoFormat(Some(f.id.clone())))));
+                let mut audio_col = vec![header().into(), auto_row("Best audio (automatic)", browser.audio.is_none(), Message::PickAudioFormat(None))];
+                audio_col.extend(audios.into_iter().map(|f| format_row(f, browser.audio.as_ref() == Some(&f.id), Message::PickAudioFormat(Some(f.id.clone())))));
+
+                scrollable(column![
+                    text("Video").size(16).font(iced::font::Font::with_name("bold")), column(video_col).spacing(2),
+                    Space::with_height(10.0),
+                    text("Audio").size(16).font(iced::font::Font::with_name("bold")), column(audio_col).spacing(2),
+                ].spacing(8)).into()
+            }
+        };
+
+        let selection = format_selector(browser.video.as_deref(), browser.audio.as_deref()).unwrap_or("automatic".into());
+        let content = column![
+            text(format!("🎞 {}", browser.title)).size(22).font(iced::font::Font::with_name("bold")),
+            dim(format!("Selected: {}", selection)),
+            container(body).style(theme::Container::Custom(Box::new(DarkCardStyle))).padding(12).width(Length::Fill).height(Length::Fill),
+            row![
+                Space::with_width(Length::Fill),
+                button("Cancel").on_press(Message::CloseModal).style(theme::Button::Destructive),
+                button("Use Selection").on_press_maybe(if browser.loading { None } else { Some(Message::ApplyFormats) }).style(theme::Button::Primary),
+            ].spacing(10),
+        ].spacing(12).padding(30);
+
+        container(content).width(Length::Fill).height(Length::Fill).style(theme::Container::Custom(Box::new(DarkBackgroundStyle))).into()
+    }
+
     fn view_dashboard(&self) -> Element<'_, Message> {
         let btn_text = if self.is_analyzing { "Analyzing..." } else { "Download" };
         let input_row = row![
@@ -300,25 +801,77 @@ impl YtDownloader {
         ].spacing(10);
         let items: Element<Message> = column(self.queue.iter().map(|item| {
             let (status_icon, status_color) = match &item.status {
-                DownloadStatus::Queued => ("⏳", hex_color("#a6adc8")), DownloadStatus::Downloading => ("🚀", hex_color("#89b4fa")), DownloadStatus::Finished => ("✅", hex_color("#a6e3a1")), DownloadStatus::Failed(_) => ("❌", hex_color("#f38ba8")), DownloadStatus::Cancelled => ("⛔", hex_color("#fab387")),
+                DownloadStatus::Queued => ("⏳", hex_color("#a6adc8")),
+                DownloadStatus::Downloading if matches!(item.live.phase, Phase::Merging | Phase::PostProcessing(_)) => ("⚙", hex_color("#cba6f7")),
+                DownloadStatus::Downloading => ("🚀", hex_color("#89b4fa")), DownloadStatus::Paused => ("⏸", hex_color("#f9e2af")), DownloadStatus::Finished => ("✅", hex_color("#a6e3a1")), DownloadStatus::Failed(e) => (e.kind.icon(), hex_color("#f38ba8")), DownloadStatus::Cancelled => ("⛔", hex_color("#fab387")),
             };
             let info_text = match &item.status {
-                DownloadStatus::Failed(e) => format!("Error: {}", e), DownloadStatus::Downloading => format!("{} | {}", item.speed, item.total_size), DownloadStatus::Finished => format!("Completed: {}", item.total_size), _ => String::new(),
+                DownloadStatus::Failed(e) => format!("{}: {}", e.kind.label(), e.message), DownloadStatus::Downloading => match &item.live.phase {
+                    Phase::Downloading => {
+                        let stream = item.live.percent().map(|p| format!("{} {:.0}%", item.live.stage, p)).unwrap_or(item.live.stage.clone());
+                        let mut parts = vec![stream, item.speed.clone(), item.total_size.clone()];
+                        parts.extend(item.live.eta_label().map(|e| format!("ETA {}", e)));
+                        parts.extend(item.live.fragments_label());
+                        parts.join(" | ")
+                    }
+                    Phase::Starting => "Starting...".into(),
+                    _ => format!("{}... | {}", item.live.stage, item.total_size),
+                }, DownloadStatus::Finished => format!("Completed: {}", item.total_size), DownloadStatus::Cancelled | DownloadStatus::Paused => item.speed.clone(),
+                DownloadStatus::Queued => format_selector(item.video_format.as_deref(), item.audio_format.as_deref()).map(|f| format!("Format: {}", f)).unwrap_or_default(),
             };
             let buttons = match item.status {
-                DownloadStatus::Downloading => row![button(text("✖").size(12)).on_press(Message::CancelDownload(item.id)).style(theme::Button::Destructive)],
+                DownloadStatus::Downloading | DownloadStatus::Queued => row![
+                    button(text("⏸").size(12)).on_press(Message::PauseDownload(item.id)).style(theme::Button::Secondary),
+                    button(text("✖").size(12)).on_press(Message::CancelDownload(item.id)).style(theme::Button::Destructive)
+                ].spacing(5),
+                DownloadStatus::Paused => row![
+                    button(text("▶").size(12)).on_press_maybe((!item.stopping).then_some(Message::ResumeDownload(item.id))).style(theme::Button::Primary),
+                    button(text("✖").size(12)).on_press(Message::CancelDownload(item.id)).style(theme::Button::Destructive)
+                ].spacing(5),
+                // A scheduled retry can be run now or called off
+                DownloadStatus::Failed(_) if item.next_retry.is_some() => row![
+                    button(text("↻").size(12)).on_press(Message::RetryDownload(item.id)).style(theme::Button::Secondary),
+                    button(text("✖").size(12)).on_press(Message::CancelDownload(item.id)).style(theme::Button::Destructive)
+                ].spacing(5),
                 _ => row![button(text("↻").size(12)).on_press(Message::RetryDownload(item.id)).style(theme::Button::Secondary)]
             };
+            let copy_btn = button(text("📋").size(12)).on_press(Message::CopyCommand(item.id)).style(theme::Button::Secondary);
+            let log_btn = button(text("📜").size(12)).on_press(Message::ToggleLogPanel(item.id)).style(if self.log_panel == Some(item.id) { theme::Button::Primary } else { theme::Button::Secondary });
+            let formats_btn = button(text("🎞").size(12)).on_press_maybe(if matches!(item.status, DownloadStatus::Downloading) { None } else { Some(Message::OpenFormatBrowser(item.id)) }).style(theme::Button::Secondary);
+            let retry_line = match (&item.status, item.next_retry) {
+                (DownloadStatus::Failed(_), Some(at)) => Some(format!("↻ Attempt {}/{} in {}s", item.attempts + 1, self.retry_policy.max_attempts, at.saturating_sub(unix_now()))),
+                (DownloadStatus::Downloading, _) if item.attempts > 0 => Some(format!("↻ Attempt {}/{}", item.attempts + 1, self.retry_policy.max_attempts.max(item.attempts + 1))),
+                _ => None,
+            };
+            let advice = match &item.status { DownloadStatus::Failed(e) => Some(e.kind.advice()), _ => None };
+            let notice = item.notices.last().map(|n| match item.notices.len() {
+                1 => format!("⚠ {}", n),
+                count => format!("⚠ {} (+{} more, see log)", n, count - 1),
+            });
             
             container(column![
-                row![text(status_icon), text(&item.title).width(Length::Fill).size(14), text(format!("{:.1}%", item.progress)).style(theme::Text::Color(status_color)), buttons].spacing(10).align_items(alignment::Alignment::Center),
+                row![text(status_icon), text(&item.title).width(Length::Fill).size(14), text(format!("{:.1}%", item.progress)).style(theme::Text::Color(status_color)), copy_btn, log_btn, formats_btn, buttons].spacing(10).align_items(alignment::Alignment::Center),
                 progress_bar(0.0..=100.0, item.progress).height(6).style(theme::ProgressBar::Custom(Box::new(BarStyle { color: status_color }))),
                 text(info_text).size(10).style(theme::Text::Color(hex_color("#bac2de")))
-            ].spacing(8)).style(theme::Container::Custom(Box::new(DarkCardStyle))).padding(12).into()
+            ].push_maybe(retry_line.map(|r| text(r).size(10).style(theme::Text::Color(hex_color("#89b4fa"))))).push_maybe(advice.map(|a| text(a).size(10).style(theme::Text::Color(hex_color("#f38ba8"))))).push_maybe(notice.map(|n| text(n).size(10).style(theme::Text::Color(hex_color("#f9e2af"))))).spacing(8)).style(theme::Container::Custom(Box::new(DarkCardStyle))).padding(12).into()
         }).collect::<Vec<_>>()).spacing(10).into();
-        let footer = row![text(&self.tool_status).size(12).style(theme::Text::Color(hex_color("#fab387"))), Space::with_width(Length::Fill), button("Update Tools").on_press(Message::CheckForUpdates).style(theme::Button::Destructive).padding(5)].align_items(alignment::Alignment::Center);
+        let versions = Tool::ALL.iter().map(|t| format!("{} {}", t.label(), self.tool_info.get(t).and_then(|i| i.version.as_deref()).unwrap_or("-"))).collect::<Vec<_>>().join("  ·  ");
+        let footer = row![text(&self.tool_status).size(12).style(theme::Text::Color(hex_color("#fab387"))), Space::with_width(Length::Fill), text(versions).size(12).style(theme::Text::Color(hex_color("#a6adc8"))), button("Update Tools").on_press(Message::CheckForUpdates).style(theme::Button::Destructive).padding(5)].align_items(alignment::Alignment::Center);
         
-        container(column![input_row, Space::with_height(10.0), scrollable(items), Space::with_height(10.0), footer].padding(20)).style(theme::Container::Custom(Box::new(DarkBackgroundStyle))).width(Length::Fill).height(Length::Fill).into()
+        let log_panel: Element<Message> = match self.log_panel.and_then(|id| self.queue.iter().find(|x| x.id == id)) {
+            Some(item) => container(column![
+                row![
+                    text(format!("Log: {}", item.title)).size(13).width(Length::Fill),
+                    button(text("Copy").size(12)).on_press(Message::CopyLog(item.id)).style(theme::Button::Secondary),
+                    button(text("Open Log File").size(12)).on_press(Message::OpenLogFile(item.id)).style(theme::Button::Secondary),
+                    button(text("✖").size(12)).on_press(Message::ToggleLogPanel(item.id)).style(theme::Button::Secondary),
+                ].spacing(10).align_items(alignment::Alignment::Center),
+                scrollable(column(item.log.iter().map(|l| text(l).size(11).font(iced::Font::MONOSPACE).style(theme::Text::Color(hex_color("#bac2de"))).into()).collect::<Vec<_>>()).width(Length::Fill)).height(180),
+            ].spacing(8)).style(theme::Container::Custom(Box::new(DarkCardStyle))).padding(12).into(),
+            None => Space::with_height(0.0).into(),
+        };
+
+        container(column![input_row, Space::with_height(10.0), scrollable(items).height(Length::Fill), log_panel, Space::with_height(10.0), footer].padding(20)).style(theme::Container::Custom(Box::new(DarkBackgroundStyle))).width(Length::Fill).height(Length::Fill).into()
     }
 
     fn view_audio_settings(&self) -> Element<'_, Message> {
@@ -331,10 +884,24 @@ impl YtDownloader {
     }
 
     fn view_video_settings(&self) -> Element<'_, Message> {
+        let q = &self.settings.quality;
         let col = column![
             text("Video & Post-Processing").size(20).font(iced::font::Font::with_name("bold")),
             row![text("Container Preference:"), pick_list(&crate::types::Container::ALL[..], Some(self.settings.container), Message::ContainerChanged)].spacing(20),
             row![text("Video Type:"), pick_list(&VideoType::ALL[..], Some(self.settings.video_type), Message::VideoTypeChanged)].spacing(20),
+
+            text("Quality").size(16).font(iced::font::Font::with_name("bold")),
+            row![text("Max Resolution:"), pick_list(&ResolutionCap::ALL[..], Some(q.max_resolution), Message::MaxResolutionChanged)].spacing(20),
+            row![text("Max FPS:"), pick_list(&FpsCap::ALL[..], Some(q.max_fps), Message::MaxFpsChanged)].spacing(20),
+            row![text("Video Codec:"), pick_list(&VideoCodecPref::ALL[..], Some(q.video_codec), Message::VideoCodecChanged)].spacing(20),
+            row![text("Audio Codec:"), pick_list(&AudioCodecPref::ALL[..], Some(q.audio_codec), Message::AudioCodecChanged)].spacing(20),
+            row![text("Dynamic Range:"), pick_list(&DynamicRangePref::ALL[..], Some(q.dynamic_range), Message::DynamicRangeChanged)].spacing(20),
+            row![
+                text("Max Filesize (e.g. 500M, 2G):"),
+                text_input("No limit", &q.max_filesize).on_input(Message::MaxFilesizeChanged).width(100),
+                text(if !q.max_filesize.trim().is_empty() && q.filesize_limit().is_none() { "Not a size, ignored" } else { "" }).size(12).style(theme::Text::Color(hex_color("#f38ba8"))),
+            ].spacing(10).align_items(alignment::Alignment::Center),
+            text(format!("-S {}  -f {}", q.sort_expr(self.settings.video_type).unwrap_or("(default)".into()), q.format_expr())).size(12).style(theme::Text::Color(hex_color("#bac2de"))),
             
             row![text("Filename Format:"), pick_list(&FilenameTemplate::ALL[..], Some(self.settings.filename_style), Message::FilenameStyleChanged)].spacing(20),
             
@@ -359,25 +926,112 @@ impl YtDownloader {
             toggler(Some("Embed Thumbnail".to_string()), self.settings.embed_thumb, Message::ToggleEmbedThumb).width(Length::Fill),
         ].spacing(20).padding(20);
         
-        container(col).style(theme::Container::Custom(Box::new(DarkBackgroundStyle))).width(Length::Fill).height(Length::Fill).into()
+        container(scrollable(col)).style(theme::Container::Custom(Box::new(DarkBackgroundStyle))).width(Length::Fill).height(Length::Fill).into()
+    }
+
+    fn view_tool_path(&self, tool: Tool, label: &str) -> Element<'_, Message> {
+        // Looked up with the version by `tool_info_task`; `view` runs too often to stat files and scan PATH
+        let info = self.tool_info.get(&tool);
+        let status = match info.and_then(|i| i.location.as_ref()) {
+            Some((path, origin)) => format!("{} ({})", path.to_string_lossy(), origin.as_str()),
+            None => "Not found, use Update Tools to install".to_string(),
+        };
+        let missing_override = info.is_some_and(|i| i.override_missing);
+        row![
+            button(text(label)).on_press(Message::PickToolPath(tool)),
+            button("Reset").on_press_maybe(self.tool_paths.get(tool).map(|_| Message::ClearToolPath(tool))).style(theme::Button::Secondary),
+            text(status).size(12),
+        ].push_maybe(missing_override.then(|| text("custom path missing").size(12).style(theme::Text::Color(hex_color("#f38ba8")))))
+            .spacing(10).align_items(alignment::Alignment::Center).into()
+    }
+
+    fn view_tool_channel(&self, tool: Tool) -> Element<'_, Message> {
+        let channel = self.tool_channels.get(tool);
+        let placeholder = match tool { Tool::YtDlp => "2024.08.06", Tool::Ffmpeg => "7.0.2" };
+        let backups = self.tool_info.get(&tool).map(|i| i.backups.as_slice()).unwrap_or_default();
+        column![
+            row![text(format!("{} updates:", tool.label())), pick_list(&UpdateChannel::ALL[..], Some(channel.channel), move |c| Message::ToolChannelChanged(tool, c))]
+                .push_maybe((channel.channel == UpdateChannel::Pinned).then(|| text_input(placeholder, &channel.pinned_version).on_input(move |v| Message::PinnedVersionChanged(tool, v)).width(160)))
+                .spacing(10).align_items(alignment::Alignment::Center),
+            row(backups.iter().map(|b| {
+                button(text(format!("Roll back to {}", b.version)).size(12)).on_press(Message::RollbackTool(tool, b.path.clone())).style(theme::Button::Secondary).into()
+            }).collect::<Vec<_>>()).spacing(8),
+        ].spacing(8).into()
+    }
+
+    fn view_proxy_table(&self) -> Element<'_, Message> {
+        if self.proxy_pool.len() == 0 { return Space::with_height(0.0).into(); }
+        let header = row![
+            text("Proxy").size(12).width(Length::FillPortion(4)), text("Check").size(12).width(Length::FillPortion(3)),
+            text("Latency").size(12).width(Length::FillPortion(1)), text("OK / Failed").size(12).width(Length::FillPortion(1)),
+        ].spacing(10);
+        let rows = self.proxy_pool.entries.iter().map(|e| {
+            let (check, color) = match &e.check {
+                Some(CheckOutcome::Alive { status, .. }) => (format!("Alive (HTTP {})", status), hex_color("#a6e3a1")),
+                Some(CheckOutcome::Dead(err)) => (format!("Dead: {}", err), hex_color("#f38ba8")),
+                Some(CheckOutcome::Unchecked(why)) => (format!("Not checked: {}", why), hex_color("#a6adc8")),
+                None if !e.stats.is_healthy() => ("Excluded after failures".to_string(), hex_color("#fab387")),
+                None => ("Not checked".to_string(), hex_color("#a6adc8")),
+            };
+            let latency = e.stats.latency.map(|l| format!("{} ms", l.as_millis())).unwrap_or("-".into());
+            row![
+                text(&e.raw).size(11).width(Length::FillPortion(4)),
+                text(check).size(11).width(Length::FillPortion(3)).style(theme::Text::Color(color)),
+                text(latency).size(11).width(Length::FillPortion(1)),
+                text(format!("{} / {}", e.stats.successes, e.stats.failures)).size(11).width(Length::FillPortion(1)),
+            ].spacing(10).into()
+        }).collect::<Vec<_>>();
+        container(column![header, scrollable(column(rows).spacing(4)).height(200)].spacing(8))
+            .style(theme::Container::Custom(Box::new(DarkCardStyle))).padding(12).into()
     }
 
     fn view_advanced_settings(&self) -> Element<'_, Message> {
         let col = column![
             text("Advanced & Network").size(20).font(iced::font::Font::with_name("bold")),
             row![button("Output Folder").on_press(Message::PickOutputDir), text(self.output_dir.to_string_lossy()).size(12)].spacing(10).align_items(alignment::Alignment::Center),
+            self.view_tool_path(Tool::YtDlp, "yt-dlp Binary"),
+            self.view_tool_path(Tool::Ffmpeg, "FFmpeg Binary"),
+            self.view_tool_channel(Tool::YtDlp),
+            self.view_tool_channel(Tool::Ffmpeg),
+            row![text("Keep previous binaries:"), pick_list(&KEEP_CHOICES[..], Some(self.keep_tool_backups), Message::KeepBackupsChanged)].spacing(10).align_items(alignment::Alignment::Center),
+            toggler(Some("Allow tool downloads without a published checksum".to_string()), self.allow_unverified_tools, Message::ToggleAllowUnverified).width(Length::Fill),
             row![button("Cookie File").on_press(Message::PickCookieFile), text(self.cookie_path.as_ref().map(|p| p.to_string_lossy().to_string()).unwrap_or("None".into())).size(12)].spacing(10).align_items(alignment::Alignment::Center),
             
             row![text("Proxy Protocol:"), pick_list(&ProxyProtocol::ALL[..], Some(self.selected_proxy_proto), Message::ProxyProtocolChanged)].spacing(20).align_items(alignment::Alignment::Center),
             row![text("Manual Proxy"), text_input("ip:port:user:pass", &self.manual_proxy).on_input(Message::ManualProxyChanged)].spacing(10).align_items(alignment::Alignment::Center),
-            row![button("Load Proxy List").on_press(Message::PickProxyList), text(format!("{} Loaded", self.proxy_list.len()))].spacing(10).align_items(alignment::Alignment::Center),
+            row![
+                button("Load Proxy List").on_press(Message::PickProxyList),
+                text(format!("{} Loaded, {} healthy", self.proxy_pool.len(), self.proxy_pool.healthy_count())),
+                button("Reset Health").on_press_maybe((self.proxy_pool.healthy_count() < self.proxy_pool.len()).then_some(Message::ResetProxyHealth)).style(theme::Button::Secondary),
+            ].spacing(10).align_items(alignment::Alignment::Center),
+            row![
+                text("Proxy Test URL"), text_input(config::DEFAULT_PROXY_TEST_URL, &self.proxy_test_url).on_input(Message::ProxyTestUrlChanged),
+                button(text(if self.checking_proxies { "Checking..." } else { "Check Proxies" })).on_press_maybe((!self.checking_proxies && self.proxy_pool.len() > 0).then_some(Message::CheckProxies)),
+                button("Drop Dead").on_press_maybe(self.proxy_pool.entries.iter().any(|e| matches!(e.check, Some(CheckOutcome::Dead(_)))).then_some(Message::DropDeadProxies)).style(theme::Button::Destructive),
+            ].spacing(10).align_items(alignment::Alignment::Center),
+            self.view_proxy_table(),
+            column(self.proxy_rejected.iter().map(|r| {
+                text(format!("Line {}: {} ({})", r.line_no, r.text, r.reason)).size(11).style(theme::Text::Color(hex_color("#f38ba8"))).into()
+            }).collect::<Vec<_>>()).spacing(4),
             
             row![text("Max Downloads:"), text_input("3", &self.max_concurrent_input).on_input(Message::MaxConcurrentChanged).width(50)].spacing(10).align_items(alignment::Alignment::Center),
 
             toggler(Some("Use SponsorBlock (Remove Ads)".to_string()), self.settings.sponsorblock, Message::ToggleSponsorBlock).width(Length::Fill),
+            toggler(Some("Delete partial files on cancel".to_string()), self.delete_partials_on_cancel, Message::ToggleDeletePartials).width(Length::Fill),
+            toggler(Some("Retry failed downloads automatically".to_string()), self.retry_policy.enabled, Message::ToggleRetry).width(Length::Fill),
+            row![
+                text("Max attempts:"), pick_list(&ATTEMPT_CHOICES[..], Some(self.retry_policy.max_attempts), Message::RetryAttemptsChanged),
+                text("First delay (s):"), pick_list(&BASE_DELAY_CHOICES[..], Some(self.retry_policy.base_delay_secs), Message::RetryBaseDelayChanged),
+                text("Max delay (s):"), pick_list(&MAX_DELAY_CHOICES[..], Some(self.retry_policy.max_delay_secs), Message::RetryMaxDelayChanged),
+            ].spacing(10).align_items(alignment::Alignment::Center),
+            column(RetryClass::ALL.iter().map(|&class| {
+                checkbox(class.as_str(), self.retry_policy.classes.contains(&class)).on_toggle(move |v| Message::RetryClassToggled(class, v)).into()
+            }).collect::<Vec<_>>()).spacing(8),
             text("Playlist Items (e.g. 1,2,5-10):"), text_input("1-10", &self.settings.playlist_items).on_input(Message::PlaylistItemsChanged),
+            text(self.playlist_items_error.as_deref().unwrap_or("")).size(12).style(theme::Text::Color(hex_color("#f38ba8"))),
             text("Rate Limit (e.g. 5M, 500K):"), text_input("Unlimited", &self.settings.rate_limit).on_input(Message::RateLimitChanged),
-            text("Custom Arguments (Paste extra flags here):"), text_input("--geo-bypass --user-agent ...", &self.settings.custom_args).on_input(Message::CustomArgsChanged),
+            text("Custom Arguments (Paste extra flags here):"), text_input("--geo-bypass --user-agent \"Mozilla/5.0 ...\"", &self.settings.custom_args).on_input(Message::CustomArgsChanged),
+            text(self.custom_args_error.as_deref().unwrap_or("")).size(12).style(theme::Text::Color(hex_color("#f38ba8"))),
         ].spacing(15).padding(20);
         
         container(scrollable(col)).style(theme::Container::Custom(Box::new(DarkBackgroundStyle))).width(Length::Fill).height(Length::Fill).into()
@@ -0,0 +1,260 @@
+use serde_json::Value;
+
+// A parsed "Playlist Items" value such as `1,2,5-10` or `20-`. Indices are 1-based like yt-dlp's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaylistRange { spans: Vec<(usize, Option<usize>)> }
+
+impl PlaylistRange {
+    // Empty input means "every entry" and parses to `None`.
+    pub fn parse(input: &str) -> Result<Option<PlaylistRange>, String> {
+        let input = input.trim();
+        if input.is_empty() { return Ok(None); }
+        let mut spans = Vec::new();
+        for part in input.split(',') {
+            let part = part.trim();
+            if part.is_empty() { return Err("empty item between commas".into()); }
+            let span = match part.split_once('-') {
+                Some((start, end)) => {
+                    let start = parse_index(start.trim(), part)?;
+                    let end = end.trim();
+                    if end.is_empty() { (start, None) } else {
+                        let end = parse_index(end, part)?;
+                        if end < start { return Err(format!("'{}' ends before it starts", part)); }
+                        (start, Some(end))
+                    }
+                }
+                None => { let i = parse_index(part, part)?; (i, Some(i)) }
+            };
+            spans.push(span);
+        }
+        Ok(Some(PlaylistRange { spans }))
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.spans.iter().any(|&(start, end)| index >= start && end.is_none_or(|e| index <= e))
+    }
+
+    // Normalized form for `--playlist-items`.
+    pub fn to_arg(&self) -> String {
+        self.spans.iter().map(|&(start, end)| match end {
+            Some(e) if e == start => start.to_string(),
+            Some(e) => format!("{}-{}", start, e),
+            None => format!("{}-", start),
+        }).collect::<Vec<_>>().join(",")
+    }
+}
+
+fn parse_index(s: &str, part: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err(format!("'{}': playlist indices start at 1", part)),
+        Ok(i) => Ok(i),
+        Err(_) => Err(format!("'{}' is not a number or range", part)),
+    }
+}
+
+// 1-based position of each entry in the original playlist. When `--playlist-items` was applied
+// yt-dlp lists the picked indices in `requested_entries`, so positions in `entries` can't be trusted.
+pub fn entry_indices(json: &Value, entries: &[Value]) -> Vec<usize> {
+    let requested: Option<Vec<usize>> = json.get("requested_entries").and_then(|v| v.as_array())
+        .map(|a| a.iter().filter_map(|i| i.as_u64().map(|i| i as usize)).collect());
+    entries.iter().enumerate().map(|(pos, entry)| {
+        requested.as_ref().and_then(|r| r.get(pos).copied())
+            .or(entry.get("playlist_index").and_then(|v| v.as_u64()).map(|i| i as usize))
+            .unwrap_or(pos + 1)
+    }).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct PlaylistEntry {
+    pub index: usize,
+    pub title: String,
+    pub duration: Option<f64>,
+    pub uploader: Option<String>,
+    pub url: String,
+    pub selected: bool,
+}
+
+// State of the playlist selection dialog shown after analysing a playlist.
+#[derive(Debug, Clone, Default)]
+pub struct PlaylistPicker {
+    pub title: String,
+    pub entries: Vec<PlaylistEntry>,
+    pub filter: String,
+    pub range_input: String,
+    pub range_error: Option<String>,
+    pub skipped: usize,
+}
+
+impl PlaylistPicker {
+    pub fn from_json(json: &Value, entries: &[Value], preselect: Option<&PlaylistRange>) -> Self {
+        let playlist_ie = json.get("extractor_key").and_then(|s| s.as_str());
+        let parsed: Vec<PlaylistEntry> = entries.iter().zip(entry_indices(json, entries)).filter_map(|(entry, index)| {
+            let url = entry_url(entry, playlist_ie)?;
+            Some(PlaylistEntry {
+                index,
+                title: entry.get("title").and_then(|s| s.as_str()).unwrap_or("Unknown Title").to_string(),
+                duration: entry.get("duration").and_then(|v| v.as_f64()),
+                uploader: entry.get("uploader").or(entry.get("channel")).and_then(|s| s.as_str()).map(str::to_string),
+                url,
+                selected: preselect.is_none_or(|r| r.contains(index)),
+            })
+        }).collect();
+        PlaylistPicker {
+            title: json.get("title").and_then(|s| s.as_str()).unwrap_or("Playlist").to_string(),
+            skipped: entries.len() - parsed.len(),
+            entries: parsed,
+            ..Default::default()
+        }
+    }
+
+    pub fn matches_filter(&self, entry: &PlaylistEntry) -> bool {
+        let needle = self.filter.trim().to_lowercase();
+        needle.is_empty()
+            || entry.title.to_lowercase().contains(&needle)
+            || entry.uploader.as_deref().is_some_and(|u| u.to_lowercase().contains(&needle))
+    }
+
+    // Select all / none only touch the entries the filter currently shows.
+    pub fn select_visible(&mut self, selected: bool) {
+        let visible: Vec<bool> = self.entries.iter().map(|e| self.matches_filter(e)).collect();
+        for (entry, show) in self.entries.iter_mut().zip(visible) { if show { entry.selected = selected; } }
+    }
+
+    pub fn apply_range(&mut self) {
+        match PlaylistRange::parse(&self.range_input) {
+            Ok(range) => {
+                self.range_error = None;
+                for entry in self.entries.iter_mut() { entry.selected = range.as_ref().is_none_or(|r| r.contains(entry.index)); }
+            }
+            Err(e) => self.range_error = Some(e),
+        }
+    }
+
+    pub fn selected_count(&self) -> usize { self.entries.iter().filter(|e| e.selected).count() }
+}
+
+// Turns a `--flat-playlist` entry into a URL yt-dlp can download on its own. Most extractors
+// put a full link in `url` or `webpage_url`; some (YouTube among them) may only give an id plus
+// the `ie_key` of the extractor that owns it, which is where the per-site fallbacks come in.
+pub fn entry_url(entry: &Value, playlist_ie: Option<&str>) -> Option<String> {
+    let field = |key: &str| entry.get(key).and_then(|v| v.as_str()).map(str::trim).filter(|s| !s.is_empty());
+    let is_link = |s: &str| s.starts_with("http://") || s.starts_with("https://");
+
+    if let Some(url) = ["webpage_url", "url", "original_url"].iter().filter_map(|k| field(k)).find(|u| is_link(u)) {
+        return Some(url.to_string());
+    }
+    // Without a link, `url` usually holds the bare id
+    let id = field("id").or(field("url"))?;
+    let ie = field("ie_key").or(playlist_ie)?;
+    let url = match ie {
+        "Youtube" | "YoutubeTab" => format!("https://www.youtube.com/watch?v={}", id),
+        "Vimeo" | "VimeoAlbum" | "VimeoShowcase" | "VimeoChannel" | "VimeoUser" => format!("https://vimeo.com/{}", id),
+        "Dailymotion" | "DailymotionPlaylist" | "DailymotionUser" => format!("https://www.dailymotion.com/video/{}", id),
+        "BiliBili" | "BilibiliSpaceVideo" => format!("https://www.bilibili.com/video/{}", id),
+        "TwitchVod" | "TwitchVideos" | "TwitchCollection" => format!("https://www.twitch.tv/videos/{}", id.trim_start_matches('v')),
+        _ => return None,
+    };
+    Some(url)
+}
+
+pub fn format_duration(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    let (h, m, s) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+    if h > 0 { format!("{}:{:02}:{:02}", h, m, s) } else { format!("{}:{:02}", m, s) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Value {
+        let path = format!("{}/tests/fixtures/playlists/{}", env!("CARGO_MANIFEST_DIR"), name);
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+    }
+
+    fn picker(name: &str, preselect: Option<&PlaylistRange>) -> PlaylistPicker {
+        let json = fixture(name);
+        let entries = json["entries"].as_array().unwrap().clone();
+        PlaylistPicker::from_json(&json, &entries, preselect)
+    }
+
+    fn urls(p: &PlaylistPicker) -> Vec<&str> { p.entries.iter().map(|e| e.url.as_str()).collect() }
+
+    #[test]
+    fn vimeo_showcase_falls_back_to_the_playlist_extractor() {
+        let p = picker("vimeo_showcase.json", None);
+        assert_eq!(p.title, "Staff Picks Showcase");
+        assert_eq!(urls(&p), [
+            "https://player.vimeo.com/video/912645537?h=4b2c1a9e0f",
+            "https://player.vimeo.com/video/905872316",
+            // No url and no ie_key of its own: the showcase's extractor_key decides
+            "https://vimeo.com/899120447",
+        ]);
+        assert_eq!(p.entries.iter().map(|e| e.index).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(p.entries[0].duration, Some(604.0));
+        assert_eq!(p.entries[1].title, "Night Shift");
+        assert_eq!(p.entries[2].duration, None);
+        assert_eq!(p.skipped, 0);
+    }
+
+    #[test]
+    fn soundcloud_set_keeps_api_links() {
+        let p = picker("soundcloud_set.json", None);
+        assert_eq!(urls(&p), [
+            "https://api.soundcloud.com/tracks/1532093467",
+            "https://api.soundcloud.com/tracks/1532101912",
+            "https://api.soundcloud.com/tracks/1533020754",
+        ]);
+        // Flat SoundCloud entries carry no title
+        assert!(p.entries.iter().all(|e| e.title == "Unknown Title" && e.selected));
+    }
+
+    #[test]
+    fn bandcamp_album_skips_entries_without_a_usable_url() {
+        let p = picker("bandcamp_album.json", None);
+        assert_eq!(urls(&p), ["https://halcyondrift.bandcamp.com/track/first-frost", "https://halcyondrift.bandcamp.com/track/thaw"]);
+        assert_eq!(p.skipped, 1);
+        assert_eq!(p.entries[0].duration, Some(241.5));
+    }
+
+    #[test]
+    fn youtube_ie_key_only_entries_become_watch_links() {
+        let p = picker("youtube_playlist_items.json", None);
+        assert_eq!(urls(&p), [
+            "https://www.youtube.com/watch?v=kJQP7kiw5Fk",
+            "https://www.youtube.com/watch?v=9bZkp7q19f0",
+            "https://www.youtube.com/watch?v=OPf0YbXqDm0",
+        ]);
+        assert_eq!(p.entries[1].uploader.as_deref(), Some("Ferris Fans"));
+        assert_eq!(p.entries[2].uploader.as_deref(), Some("DevTalks"));
+    }
+
+    #[test]
+    fn requested_entries_map_back_to_playlist_positions() {
+        let json = fixture("youtube_playlist_items.json");
+        let entries = json["entries"].as_array().unwrap().clone();
+        assert_eq!(entry_indices(&json, &entries), [2, 4, 7]);
+
+        let range = PlaylistRange::parse("4-7").unwrap().unwrap();
+        let p = PlaylistPicker::from_json(&json, &entries, Some(&range));
+        assert_eq!(p.entries.iter().map(|e| (e.index, e.selected)).collect::<Vec<_>>(), [(2, false), (4, true), (7, true)]);
+    }
+
+    #[test]
+    fn indices_fall_back_to_playlist_index_then_position() {
+        let json = serde_json::json!({});
+        let entries = vec![serde_json::json!({"playlist_index": 5}), serde_json::json!({})];
+        assert_eq!(entry_indices(&json, &entries), [5, 2]);
+    }
+
+    #[test]
+    fn entry_url_fallbacks() {
+        let entry = |v: Value| entry_url(&v, None);
+        assert_eq!(entry(serde_json::json!({"ie_key": "Youtube", "url": "dQw4w9WgXcQ"})).as_deref(), Some("https://www.youtube.com/watch?v=dQw4w9WgXcQ"));
+        assert_eq!(entry(serde_json::json!({"ie_key": "TwitchVod", "id": "v2024681357"})).as_deref(), Some("https://www.twitch.tv/videos/2024681357"));
+        assert_eq!(entry(serde_json::json!({"ie_key": "Dailymotion", "id": "x8abc12"})).as_deref(), Some("https://www.dailymotion.com/video/x8abc12"));
+        assert_eq!(entry(serde_json::json!({"original_url": "https://example.com/v/1", "url": "1"})).as_deref(), Some("https://example.com/v/1"));
+        assert_eq!(entry(serde_json::json!({"ie_key": "Generic", "id": "abc"})), None);
+        assert_eq!(entry(serde_json::json!({"id": "abc"})), None);
+    }
+}
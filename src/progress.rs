@@ -0,0 +1,327 @@
+use regex::Regex;
+use once_cell::sync::Lazy;
+use crate::formats::human_size;
+use crate::types::{AdvOptions, AudioFormat};
+
+static FORMATS_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\[info\] .*Downloading \d+ format\(s\): (\S+)").unwrap()
+});
+
+// yt-dlp prints these instead of its human-readable progress lines; the prefixes keep them apart
+// from everything else on stdout. Missing values come through as "NA".
+pub const DOWNLOAD_PREFIX: &str = "[gui-progress]";
+pub const POSTPROCESS_PREFIX: &str = "[gui-postprocess]";
+
+pub const PROGRESS_TEMPLATE_ARGS: [&str; 4] = [
+    "--progress-template",
+    "download:[gui-progress] %(progress.status)s|%(progress.downloaded_bytes)s|%(progress.total_bytes)s|%(progress.total_bytes_estimate)s|%(progress.speed)s|%(progress.eta)s|%(progress.fragment_index)s|%(progress.fragment_count)s",
+    "--progress-template",
+    "postprocess:[gui-postprocess] %(progress.status)s|%(progress.postprocessor)s",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Phase {
+    #[default]
+    Starting,
+    Downloading,
+    Merging,
+    PostProcessing(String),
+}
+impl Phase {
+    pub fn label(&self) -> String {
+        match self {
+            Phase::Starting => "Starting".into(),
+            Phase::Downloading => "Downloading".into(),
+            Phase::Merging => "Merging formats".into(),
+            Phase::PostProcessing(name) => match name.as_str() {
+                "ExtractAudio" => "Extracting audio".into(),
+                "EmbedThumbnail" => "Embedding thumbnail".into(),
+                "EmbedSubtitle" => "Embedding subtitles".into(),
+                "Metadata" => "Writing metadata".into(),
+                "ModifyChapters" => "Removing sponsor segments".into(),
+                other => other.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Progress {
+    pub phase: Phase,
+    pub downloaded: Option<u64>,
+    pub total: Option<u64>,
+    pub total_is_estimate: bool,
+    pub speed: Option<f64>,
+    pub eta: Option<u64>,
+    pub fragment_index: Option<u64>,
+    pub fragment_count: Option<u64>,
+    // Filled in by `StageTracker`: weighted progress over every stage and the stage's display name
+    pub overall: Option<f32>,
+    pub stage: String,
+}
+
+impl Progress {
+    pub fn percent(&self) -> Option<f32> {
+        match (self.downloaded, self.total) {
+            (Some(d), Some(t)) if t > 0 => Some((d as f64 / t as f64 * 100.0).min(100.0) as f32),
+            _ => match (self.fragment_index, self.fragment_count) {
+                (Some(i), Some(c)) if c > 0 => Some((i as f64 / c as f64 * 100.0).min(100.0) as f32),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn speed_label(&self) -> String { self.speed.map(|s| format!("{}/s", human_size(s as u64))).unwrap_or("-".into()) }
+
+    pub fn size_label(&self) -> String {
+        match self.total {
+            Some(t) if self.total_is_estimate => format!("~{}", human_size(t)),
+            Some(t) => human_size(t),
+            None => "?".into(),
+        }
+    }
+
+    pub fn eta_label(&self) -> Option<String> {
+        self.eta.map(|e| if e >= 3600 { format!("{}:{:02}:{:02}", e / 3600, (e % 3600) / 60, e % 60) } else { format!("{}:{:02}", e / 60, e % 60) })
+    }
+
+    pub fn fragments_label(&self) -> Option<String> {
+        match (self.fragment_index, self.fragment_count) {
+            (Some(i), Some(c)) => Some(format!("frag {}/{}", i, c)),
+            (Some(i), None) => Some(format!("frag {}", i)),
+            _ => None,
+        }
+    }
+}
+
+// Parses one stdout line into a progress update, or `None` for everything that isn't one.
+pub fn parse_progress_line(line: &str) -> Option<Progress> {
+    let line = line.trim();
+    if let Some(rest) = line.strip_prefix(DOWNLOAD_PREFIX) {
+        let f: Vec<&str> = rest.trim().split('|').collect();
+        if f.len() < 8 { return None; }
+        let num = |s: &str| s.trim().parse::<f64>().ok();
+        let exact = num(f[2]);
+        return Some(Progress {
+            phase: Phase::Downloading,
+            downloaded: num(f[1]).map(|v| v as u64),
+            total: exact.or(num(f[3])).map(|v| v as u64),
+            total_is_estimate: exact.is_none(),
+            speed: num(f[4]),
+            eta: num(f[5]).map(|v| v as u64),
+            fragment_index: num(f[6]).map(|v| v as u64),
+            fragment_count: num(f[7]).map(|v| v as u64),
+            ..Default::default()
+        });
+    }
+    if let Some(rest) = line.strip_prefix(POSTPROCESS_PREFIX) {
+        let (_status, name) = rest.trim().split_once('|')?;
+        return Some(Progress { phase: postprocess_phase(name.trim()), ..Default::default() });
+    }
+    // Not every post-processor reports through the template; their log prefix is enough to tell the phase
+    let name = line.strip_prefix('[')?.split_once(']')?.0;
+    matches!(name, "Merger" | "ExtractAudio" | "EmbedThumbnail" | "EmbedSubtitle" | "Metadata" | "SponsorBlock" | "ModifyChapters" | "FixupM3u8" | "VideoConvertor" | "VideoRemuxer")
+        .then(|| Progress { phase: postprocess_phase(name), ..Default::default() })
+}
+
+fn postprocess_phase(name: &str) -> Phase {
+    match name {
+        "Merger" => Phase::Merging,
+        other => Phase::PostProcessing(other.to_string()),
+    }
+}
+
+// Post-processors yt-dlp runs before format selection (its `after_filter` stage), so before anything
+// is downloaded.
+const BEFORE_DOWNLOAD: [&str; 1] = ["SponsorBlock"];
+
+// Post-processors the options will run after the download, in the order yt-dlp runs them. The merger
+// is added once the format line shows more than one stream.
+fn expected_postprocessors(opts: &AdvOptions) -> Vec<String> {
+    let mut pp = Vec::new();
+    if opts.sponsorblock { pp.push("ModifyChapters"); }
+    if opts.audio_fmt != AudioFormat::None { pp.push("ExtractAudio"); }
+    else if opts.embed_subs { pp.push("EmbedSubtitle"); }
+    if opts.embed_meta { pp.push("Metadata"); }
+    if opts.embed_thumb { pp.push("EmbedThumbnail"); }
+    pp.into_iter().map(str::to_string).collect()
+}
+
+const MERGE_WEIGHT: f32 = 0.06;
+const POSTPROCESS_WEIGHT: f32 = 0.03;
+
+// Follows one yt-dlp run through its stages (pre-download post-processors, each downloaded stream,
+// the merge, each post-processor) and turns them into a single weighted percentage, so the bar doesn't
+// jump back to 0 for the audio stream or sit still during post-processing.
+#[derive(Debug, Clone, Default)]
+pub struct StageTracker {
+    streams: usize,
+    stream: usize,
+    pre: Vec<String>,
+    post: Vec<String>,
+    // Index into `post` of the post-processor running now
+    post_current: Option<usize>,
+    overall: f32,
+}
+
+impl StageTracker {
+    pub fn new(opts: Option<&AdvOptions>) -> Self {
+        let pre = match opts { Some(o) if o.sponsorblock => vec!["SponsorBlock".to_string()], _ => Vec::new() };
+        StageTracker { streams: 1, pre, post: opts.map(expected_postprocessors).unwrap_or_default(), ..Default::default() }
+    }
+
+    // Feeds one stdout line; returns an update for the UI when the line moved anything.
+    pub fn feed(&mut self, line: &str) -> Option<Progress> {
+        let line = line.trim();
+        if let Some(caps) = FORMATS_RE.captures(line) {
+            self.streams = caps[1].split('+').count().max(1);
+            if self.streams > 1 && !self.post.iter().any(|p| p == "Merger") { self.post.insert(0, "Merger".into()); }
+            return None;
+        }
+        if let Some(rest) = line.strip_prefix("[download] ") {
+            let starts_stream = rest.strip_prefix("Destination: ").is_some_and(|dest| !is_side_file(dest))
+                || rest.ends_with("has already been downloaded");
+            if starts_stream { self.stream = (self.stream + 1).min(self.streams.max(1)); }
+        }
+
+        let mut p = parse_progress_line(line)?;
+        let (fraction, stage) = match &p.phase {
+            Phase::Starting => (0.0, p.phase.label()),
+            Phase::Downloading => {
+                self.stream = self.stream.max(1);
+                let idx = self.stream;
+                let weights = self.stream_weights();
+                let done: f32 = weights.iter().take(idx - 1).sum();
+                let cur = weights.get(idx - 1).copied().unwrap_or(0.0) * p.percent().unwrap_or(0.0) / 100.0;
+                let label = match (self.streams, idx) {
+                    (1, _) => "Downloading".to_string(),
+                    (n, 1) => format!("Downloading video (1/{})", n),
+                    (n, i) => format!("Downloading audio ({}/{})", i, n),
+                };
+                (self.pre_share() + done + cur, label)
+            }
+            // Anything running before the first stream starts can't be after the download either
+            Phase::PostProcessing(name) if BEFORE_DOWNLOAD.contains(&name.as_str()) || self.stream == 0 => {
+                if !self.pre.contains(name) { self.pre.push(name.clone()); }
+                let pos = self.pre.iter().position(|x| x == name).unwrap_or(0);
+                (self.pre.iter().take(pos).map(|n| pp_weight(n)).sum::<f32>(), p.phase.label())
+            }
+            phase => {
+                let name = match phase { Phase::PostProcessing(n) => n.clone(), _ => "Merger".to_string() };
+                // Fixups and the like aren't predictable from the options; count them as they show up
+                let pos = match self.post.iter().position(|x| *x == name) {
+                    Some(pos) => pos,
+                    None => {
+                        let pos = self.post_current.map_or(0, |i| i + 1);
+                        self.post.insert(pos, name);
+                        pos
+                    }
+                };
+                self.post_current = Some(self.post_current.map_or(pos, |i| i.max(pos)));
+                (self.pre_share() + self.download_share() + self.post.iter().take(pos).map(|n| pp_weight(n)).sum::<f32>(), p.phase.label())
+            }
+        };
+        self.overall = self.overall.max(fraction * 100.0).min(100.0);
+        p.overall = Some(self.overall);
+        p.stage = stage;
+        Some(p)
+    }
+
+    fn pre_share(&self) -> f32 { self.pre.iter().map(|n| pp_weight(n)).sum() }
+
+    fn download_share(&self) -> f32 { 1.0 - self.pre_share() - self.post.iter().map(|n| pp_weight(n)).sum::<f32>() }
+
+    // Video streams dwarf audio ones, so with two streams the first gets most of the download share
+    fn stream_weights(&self) -> Vec<f32> {
+        let share = self.download_share();
+        match self.streams {
+            2 => vec![share * 0.8, share * 0.2],
+            n => vec![share / n as f32; n],
+        }
+    }
+}
+
+fn pp_weight(name: &str) -> f32 { if name == "Merger" { MERGE_WEIGHT } else { POSTPROCESS_WEIGHT } }
+
+// Subtitles and thumbnails are downloaded too but aren't one of the format streams.
+fn is_side_file(dest: &str) -> bool {
+    let ext = dest.rsplit('.').next().unwrap_or("").to_lowercase();
+    matches!(ext.as_str(), "vtt" | "srt" | "ass" | "ttml" | "srv1" | "srv2" | "srv3" | "json3" | "lrc" | "jpg" | "jpeg" | "png" | "webp")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feeds a recorded stdout fixture through a tracker, checking on the way that the bar never goes back.
+    fn run(name: &str, opts: AdvOptions) -> Vec<Progress> {
+        let path = format!("{}/tests/fixtures/progress/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let mut tracker = StageTracker::new(Some(&opts));
+        let updates: Vec<Progress> = std::fs::read_to_string(&path).unwrap().lines().filter_map(|l| tracker.feed(l)).collect();
+        let overall: Vec<f32> = updates.iter().map(|p| p.overall.unwrap()).collect();
+        assert!(overall.windows(2).all(|w| w[0] <= w[1]), "{}: {:?}", name, overall);
+        assert!(overall.iter().all(|o| (0.0..=100.0).contains(o)), "{}: {:?}", name, overall);
+        updates
+    }
+
+    fn stages(updates: &[Progress]) -> Vec<&str> {
+        let mut stages: Vec<&str> = updates.iter().map(|p| p.stage.as_str()).collect();
+        stages.dedup();
+        stages
+    }
+
+    // The first update with the given stage
+    fn at<'a>(updates: &'a [Progress], stage: &str) -> &'a Progress { updates.iter().find(|p| p.stage == stage).unwrap() }
+
+    fn no_embeds() -> AdvOptions { AdvOptions { embed_meta: false, embed_thumb: false, ..AdvOptions::default() } }
+
+    #[test]
+    fn single_stream() {
+        let updates = run("single.txt", AdvOptions { embed_thumb: false, ..AdvOptions::default() });
+        assert_eq!(stages(&updates), ["Downloading", "Writing metadata"]);
+        assert!(updates[0].overall.unwrap() < 1.0);
+        // Halfway through the only stream is about halfway through the run
+        let mid = updates[1].overall.unwrap();
+        assert!((25.0..40.0).contains(&mid), "{}", mid);
+        assert!(at(&updates, "Writing metadata").overall.unwrap() > 95.0);
+    }
+
+    #[test]
+    fn video_and_audio_merge() {
+        let updates = run("merge.txt", AdvOptions { embed_meta: false, ..AdvOptions::default() });
+        assert_eq!(stages(&updates), ["Downloading video (1/2)", "Downloading audio (2/2)", "Merging formats", "Embedding thumbnail"]);
+        // The audio stream carries on from where the video left off instead of starting over
+        let audio = at(&updates, "Downloading audio (2/2)").overall.unwrap();
+        assert!((70.0..85.0).contains(&audio), "{}", audio);
+        assert!(at(&updates, "Merging formats").overall.unwrap() < at(&updates, "Embedding thumbnail").overall.unwrap());
+    }
+
+    #[test]
+    fn extract_audio() {
+        let updates = run("extract_audio.txt", AdvOptions { audio_fmt: AudioFormat::Mp3, embed_thumb: false, ..AdvOptions::default() });
+        assert_eq!(stages(&updates), ["Downloading", "Extracting audio", "Writing metadata"]);
+        // The size is only an estimate for fragmented downloads
+        assert!(updates[0].total_is_estimate);
+        assert!(at(&updates, "Extracting audio").overall.unwrap() < at(&updates, "Writing metadata").overall.unwrap());
+    }
+
+    #[test]
+    fn sponsorblock_runs_before_the_download() {
+        let updates = run("sponsorblock.txt", AdvOptions { sponsorblock: true, ..no_embeds() });
+        assert_eq!(stages(&updates), ["SponsorBlock", "Downloading", "Removing sponsor segments"]);
+        assert_eq!(updates[0].overall, Some(0.0));
+        // Fetching segments mustn't count the download as done
+        let downloading: Vec<f32> = updates.iter().filter(|p| p.stage == "Downloading").map(|p| p.overall.unwrap()).collect();
+        assert!(downloading[0] < 5.0, "{:?}", downloading);
+        assert!((40.0..60.0).contains(&downloading[1]), "{:?}", downloading);
+        assert!(downloading.last().unwrap() > &90.0, "{:?}", downloading);
+    }
+
+    #[test]
+    fn unknown_fixup_is_counted_as_it_shows_up() {
+        let updates = run("unknown_fixup.txt", AdvOptions { embed_thumb: false, ..AdvOptions::default() });
+        assert_eq!(stages(&updates), ["Downloading", "FixupM3u8", "Writing metadata"]);
+        assert!(at(&updates, "FixupM3u8").overall.unwrap() <= at(&updates, "Writing metadata").overall.unwrap());
+        assert!(at(&updates, "Writing metadata").overall.unwrap() < 100.0);
+    }
+}
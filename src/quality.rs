@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use regex::Regex;
+use once_cell::sync::Lazy;
+use crate::types::VideoType;
+
+static FILESIZE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)^(\d+(?:\.\d+)?)\s*([kmg])?i?b?$").unwrap()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ResolutionCap { #[default] Any, P2160, P1440, P1080, P720, P480, P360 }
+impl ResolutionCap {
+    pub const ALL: [ResolutionCap; 7] = [ResolutionCap::Any, ResolutionCap::P2160, ResolutionCap::P1440, ResolutionCap::P1080, ResolutionCap::P720, ResolutionCap::P480, ResolutionCap::P360];
+    pub fn height(self) -> Option<u32> {
+        match self { ResolutionCap::Any => None, ResolutionCap::P2160 => Some(2160), ResolutionCap::P1440 => Some(1440), ResolutionCap::P1080 => Some(1080), ResolutionCap::P720 => Some(720), ResolutionCap::P480 => Some(480), ResolutionCap::P360 => Some(360) }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self { ResolutionCap::Any => "No limit", ResolutionCap::P2160 => "2160p (4K)", ResolutionCap::P1440 => "1440p", ResolutionCap::P1080 => "1080p", ResolutionCap::P720 => "720p", ResolutionCap::P480 => "480p", ResolutionCap::P360 => "360p" }
+    }
+}
+impl std::fmt::Display for ResolutionCap { fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.as_str()) } }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FpsCap { #[default] Any, Fps60, Fps30 }
+impl FpsCap {
+    pub const ALL: [FpsCap; 3] = [FpsCap::Any, FpsCap::Fps60, FpsCap::Fps30];
+    pub fn fps(self) -> Option<u32> { match self { FpsCap::Any => None, FpsCap::Fps60 => Some(60), FpsCap::Fps30 => Some(30) } }
+    pub fn as_str(&self) -> &'static str { match self { FpsCap::Any => "No limit", FpsCap::Fps60 => "60 fps", FpsCap::Fps30 => "30 fps" } }
+}
+impl std::fmt::Display for FpsCap { fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.as_str()) } }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum VideoCodecPref { #[default] Any, Av1, Vp9, H264 }
+impl VideoCodecPref {
+    pub const ALL: [VideoCodecPref; 4] = [VideoCodecPref::Any, VideoCodecPref::Av1, VideoCodecPref::Vp9, VideoCodecPref::H264];
+    // yt-dlp's codec order is av01 > vp9 > h265 > h264, so preferring one falls back down that list
+    pub fn sort_field(self) -> Option<&'static str> {
+        match self { VideoCodecPref::Any => None, VideoCodecPref::Av1 => Some("vcodec:av01"), VideoCodecPref::Vp9 => Some("vcodec:vp9"), VideoCodecPref::H264 => Some("vcodec:h264") }
+    }
+    pub fn as_str(&self) -> &'static str { match self { VideoCodecPref::Any => "Any", VideoCodecPref::Av1 => "Prefer AV1", VideoCodecPref::Vp9 => "Prefer VP9", VideoCodecPref::H264 => "Prefer H.264" } }
+}
+impl std::fmt::Display for VideoCodecPref { fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.as_str()) } }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AudioCodecPref { #[default] Any, Opus, Aac }
+impl AudioCodecPref {
+    pub const ALL: [AudioCodecPref; 3] = [AudioCodecPref::Any, AudioCodecPref::Opus, AudioCodecPref::Aac];
+    pub fn sort_field(self) -> Option<&'static str> {
+        match self { AudioCodecPref::Any => None, AudioCodecPref::Opus => Some("acodec:opus"), AudioCodecPref::Aac => Some("acodec:aac") }
+    }
+    pub fn as_str(&self) -> &'static str { match self { AudioCodecPref::Any => "Any", AudioCodecPref::Opus => "Prefer Opus", AudioCodecPref::Aac => "Prefer AAC" } }
+}
+impl std::fmt::Display for AudioCodecPref { fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.as_str()) } }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DynamicRangePref { #[default] Any, PreferHdr, SdrOnly }
+impl DynamicRangePref {
+    pub const ALL: [DynamicRangePref; 3] = [DynamicRangePref::Any, DynamicRangePref::PreferHdr, DynamicRangePref::SdrOnly];
+    pub fn as_str(&self) -> &'static str { match self { DynamicRangePref::Any => "Any", DynamicRangePref::PreferHdr => "Prefer HDR", DynamicRangePref::SdrOnly => "SDR only" } }
+}
+impl std::fmt::Display for DynamicRangePref { fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.as_str()) } }
+
+// Resolution, fps, dynamic range and size are hard caps and go into the `-f` filters; codec and
+// HDR choices are preferences and go into `-S`, so yt-dlp still finds something when they can't be met.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualityPreset {
+    pub max_resolution: ResolutionCap,
+    pub max_fps: FpsCap,
+    pub video_codec: VideoCodecPref,
+    pub audio_codec: AudioCodecPref,
+    pub dynamic_range: DynamicRangePref,
+    pub max_filesize: String,
+}
+
+impl QualityPreset {
+    // `None` when nothing would change yt-dlp's default order.
+    pub fn sort_expr(&self, video_type: VideoType) -> Option<String> {
+        let marker = video_type.sort_marker();
+        let mut fields: Vec<String> = marker.iter().map(|m| m.to_string()).collect();
+        if self.dynamic_range == DynamicRangePref::PreferHdr { fields.push("hdr".into()); }
+        // The VR/3D markers always sorted as `<marker>,res,fps,codec`; keep that tail when no cap replaces it
+        match self.max_resolution.height() { Some(h) => fields.push(format!("res:{}", h)), None if marker.is_some() => fields.push("res".into()), None => {} }
+        match self.max_fps.fps() { Some(fps) => fields.push(format!("fps:{}", fps)), None if marker.is_some() => fields.push("fps".into()), None => {} }
+        fields.extend(self.video_codec.sort_field().map(str::to_string));
+        fields.extend(self.audio_codec.sort_field().map(str::to_string));
+        if marker.is_some() { fields.push("codec".into()); }
+        if fields.is_empty() { None } else { Some(fields.join(",")) }
+    }
+
+    // Sort used when only audio is extracted; video preferences don't apply there.
+    pub fn audio_sort_expr(&self) -> Option<String> { self.audio_codec.sort_field().map(str::to_string) }
+
+    pub fn format_expr(&self) -> String {
+        let mut video = String::new();
+        if let Some(h) = self.max_resolution.height() { video.push_str(&format!("[height<=?{}]", h)); }
+        if let Some(fps) = self.max_fps.fps() { video.push_str(&format!("[fps<=?{}]", fps)); }
+        if self.dynamic_range == DynamicRangePref::SdrOnly { video.push_str("[dynamic_range=SDR]"); }
+        let size = self.filesize_limit().map(|s| format!("[filesize<?{}]", s)).unwrap_or_default();
+        format!("bestvideo{v}{s}+bestaudio/best{v}{s}", v = video, s = size)
+    }
+
+    // The cap in yt-dlp's size syntax (`500M`, `1.5G`), or `None` when empty or not understood.
+    pub fn filesize_limit(&self) -> Option<String> {
+        let caps = FILESIZE_RE.captures(self.max_filesize.trim())?;
+        Some(format!("{}{}", &caps[1], caps.get(2).map(|u| u.as_str().to_uppercase()).unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(f: impl FnOnce(&mut QualityPreset)) -> QualityPreset { let mut p = QualityPreset::default(); f(&mut p); p }
+
+    #[test]
+    fn defaults_leave_yt_dlp_alone() {
+        let p = QualityPreset::default();
+        assert_eq!(p.sort_expr(VideoType::Normal), None);
+        assert_eq!(p.audio_sort_expr(), None);
+        assert_eq!(p.format_expr(), "bestvideo+bestaudio/best");
+    }
+
+    #[test]
+    fn vr_and_3d_markers_keep_the_original_sort() {
+        let p = QualityPreset::default();
+        assert_eq!(p.sort_expr(VideoType::VR360).as_deref(), Some("vr,res,fps,codec"));
+        assert_eq!(p.sort_expr(VideoType::ThreeD).as_deref(), Some("3d,res,fps,codec"));
+    }
+
+    #[test]
+    fn resolution_caps() {
+        for cap in ResolutionCap::ALL {
+            let p = preset(|p| p.max_resolution = cap);
+            match cap.height() {
+                Some(h) => {
+                    assert_eq!(p.sort_expr(VideoType::Normal), Some(format!("res:{}", h)));
+                    assert_eq!(p.sort_expr(VideoType::VR360), Some(format!("vr,res:{},fps,codec", h)));
+                    assert_eq!(p.format_expr(), format!("bestvideo[height<=?{h}]+bestaudio/best[height<=?{h}]", h = h));
+                }
+                None => assert_eq!(p.format_expr(), "bestvideo+bestaudio/best"),
+            }
+        }
+    }
+
+    #[test]
+    fn fps_caps() {
+        for cap in FpsCap::ALL {
+            let p = preset(|p| p.max_fps = cap);
+            match cap.fps() {
+                Some(fps) => {
+                    assert_eq!(p.sort_expr(VideoType::Normal), Some(format!("fps:{}", fps)));
+                    assert_eq!(p.sort_expr(VideoType::ThreeD), Some(format!("3d,res,fps:{},codec", fps)));
+                    assert_eq!(p.format_expr(), format!("bestvideo[fps<=?{f}]+bestaudio/best[fps<=?{f}]", f = fps));
+                }
+                None => assert_eq!(p.sort_expr(VideoType::Normal), None),
+            }
+        }
+    }
+
+    #[test]
+    fn codec_preferences_sort_but_never_filter() {
+        for codec in VideoCodecPref::ALL {
+            let p = preset(|p| p.video_codec = codec);
+            assert_eq!(p.sort_expr(VideoType::Normal).as_deref(), codec.sort_field());
+            assert_eq!(p.format_expr(), "bestvideo+bestaudio/best");
+        }
+        for codec in AudioCodecPref::ALL {
+            let p = preset(|p| p.audio_codec = codec);
+            assert_eq!(p.sort_expr(VideoType::Normal).as_deref(), codec.sort_field());
+            assert_eq!(p.audio_sort_expr().as_deref(), codec.sort_field());
+        }
+    }
+
+    #[test]
+    fn dynamic_range() {
+        let hdr = preset(|p| p.dynamic_range = DynamicRangePref::PreferHdr);
+        assert_eq!(hdr.sort_expr(VideoType::Normal).as_deref(), Some("hdr"));
+        assert_eq!(hdr.format_expr(), "bestvideo+bestaudio/best");
+        let sdr = preset(|p| p.dynamic_range = DynamicRangePref::SdrOnly);
+        assert_eq!(sdr.sort_expr(VideoType::Normal), None);
+        assert_eq!(sdr.format_expr(), "bestvideo[dynamic_range=SDR]+bestaudio/best[dynamic_range=SDR]");
+    }
+
+    #[test]
+    fn everything_at_once() {
+        let p = QualityPreset {
+            max_resolution: ResolutionCap::P720, max_fps: FpsCap::Fps60, video_codec: VideoCodecPref::Av1,
+            audio_codec: AudioCodecPref::Opus, dynamic_range: DynamicRangePref::PreferHdr, max_filesize: "500M".into(),
+        };
+        assert_eq!(p.sort_expr(VideoType::VR360).as_deref(), Some("vr,hdr,res:720,fps:60,vcodec:av01,acodec:opus,codec"));
+        assert_eq!(p.sort_expr(VideoType::Normal).as_deref(), Some("hdr,res:720,fps:60,vcodec:av01,acodec:opus"));
+        assert_eq!(p.format_expr(), "bestvideo[height<=?720][fps<=?60][filesize<?500M]+bestaudio/best[height<=?720][fps<=?60][filesize<?500M]");
+    }
+
+    #[test]
+    fn filesize_limit_parsing() {
+        let limit = |s: &str| preset(|p| p.max_filesize = s.into()).filesize_limit();
+        assert_eq!(limit("500M").as_deref(), Some("500M"));
+        assert_eq!(limit("1.5GiB").as_deref(), Some("1.5G"));
+        assert_eq!(limit("700mb").as_deref(), Some("700M"));
+        assert_eq!(limit(" 2 g ").as_deref(), Some("2G"));
+        assert_eq!(limit("300k").as_deref(), Some("300K"));
+        assert_eq!(limit("1048576").as_deref(), Some("1048576"));
+        assert_eq!(limit(""), None);
+        assert_eq!(limit("garbage"), None);
+        assert_eq!(limit("1.5TB"), None);
+        assert_eq!(limit("-5M"), None);
+        // A size that isn't understood is left out rather than breaking the expression
+        assert_eq!(preset(|p| p.max_filesize = "lots".into()).format_expr(), "bestvideo+bestaudio/best");
+    }
+}
@@ -0,0 +1,57 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::errors::ErrorKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RetryClass { RateLimited, ServerErrors, Network, Proxy }
+impl RetryClass {
+    pub const ALL: [RetryClass; 4] = [RetryClass::RateLimited, RetryClass::ServerErrors, RetryClass::Network, RetryClass::Proxy];
+    pub fn matches(self, kind: ErrorKind) -> bool {
+        match self {
+            RetryClass::RateLimited => kind == ErrorKind::RateLimited,
+            RetryClass::ServerErrors => matches!(kind, ErrorKind::Http(code) if code >= 500),
+            RetryClass::Network => kind == ErrorKind::Network,
+            RetryClass::Proxy => kind == ErrorKind::Proxy,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self { RetryClass::RateLimited => "Rate limiting (HTTP 429)", RetryClass::ServerErrors => "Server errors (HTTP 5xx)", RetryClass::Network => "Timeouts and network errors", RetryClass::Proxy => "Proxy errors" }
+    }
+}
+
+pub const ATTEMPT_CHOICES: [u32; 6] = [1, 2, 3, 5, 8, 10];
+pub const BASE_DELAY_CHOICES: [u64; 5] = [5, 10, 30, 60, 120];
+pub const MAX_DELAY_CHOICES: [u64; 5] = [60, 300, 900, 1800, 3600];
+
+// `max_attempts` counts the first run too, so 3 means up to two automatic retries.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    pub enabled: bool,
+    pub max_attempts: u32,
+    pub base_delay_secs: u64,
+    pub max_delay_secs: u64,
+    pub classes: Vec<RetryClass>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { enabled: true, max_attempts: 3, base_delay_secs: 10, max_delay_secs: 300, classes: RetryClass::ALL.to_vec() }
+    }
+}
+
+impl RetryPolicy {
+    // When to try again after the given attempt failed, or `None` if it shouldn't be retried.
+    pub fn next_retry(&self, kind: ErrorKind, attempts: u32) -> Option<u64> {
+        if !self.enabled || attempts >= self.max_attempts || !self.classes.iter().any(|c| c.matches(kind)) { return None; }
+        Some(unix_now() + self.delay(attempts))
+    }
+
+    // Doubles with every attempt: base, 2*base, 4*base... capped at `max_delay_secs`.
+    pub fn delay(&self, attempts: u32) -> u64 {
+        self.base_delay_secs.saturating_mul(1u64 << attempts.saturating_sub(1).min(20)).min(self.max_delay_secs)
+    }
+}
+
+// Retry times are stored as Unix seconds so a scheduled retry survives a restart.
+pub fn unix_now() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) }
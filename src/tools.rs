@@ -1,24 +1,211 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use md5::Md5;
 use tokio::process::Command as TokioCommand;
-use regex::Regex;
-use once_cell::sync::Lazy;
-use crate::types::{GitHubRelease, ProxyProtocol};
-
-static FFMPEG_DATE_RE: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"ffmpeg version (\d{4}-\d{2}-\d{2})").unwrap()
-});
+use std::cmp::Ordering;
+use crate::types::ProxyProtocol;
+use crate::versions::{FfmpegVersion, YtDlpVersion, latest_from_feed};
+use crate::proxy::ProxySpec;
+use crate::backups::{self, Backup};
+use crate::config::write_atomic_with;
 
+// Anything `ProxySpec` can't make sense of is passed through for yt-dlp to judge.
 pub fn format_proxy(raw: &str, protocol: ProxyProtocol) -> String {
-    if raw.contains("://") { return raw.to_string(); }
-    let p: Vec<&str> = raw.split(':').collect();
-    let scheme = protocol.as_str();
-    if p.len() == 4 { format!("{}://{}:{}@{}:{}", scheme, p[2], p[3], p[0], p[1]) } 
-    else if p.len() == 2 { format!("{}://{}:{}", scheme, p[0], p[1]) } 
-    else { raw.to_string() }
+    ProxySpec::parse(raw).map(|spec| spec.to_url(protocol)).unwrap_or_else(|_| raw.trim().to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Tool { YtDlp, Ffmpeg }
+
+impl Tool {
+    pub const ALL: [Tool; 2] = [Tool::YtDlp, Tool::Ffmpeg];
+    pub fn label(self) -> &'static str { match self { Tool::YtDlp => "yt-dlp", Tool::Ffmpeg => "FFmpeg" } }
+
+    pub fn binary_name(self) -> &'static str {
+        match (self, cfg!(windows)) {
+            (Tool::YtDlp, true) => "yt-dlp.exe", (Tool::YtDlp, false) => "yt-dlp",
+            (Tool::Ffmpeg, true) => "ffmpeg.exe", (Tool::Ffmpeg, false) => "ffmpeg",
+        }
+    }
+
+    // Where the updater installs its copy.
+    pub fn install_path(self) -> PathBuf { tools_dir().join(self.binary_name()) }
+
+    // The one place that decides which binary runs: the user's override, then the managed copy, then
+    // whatever PATH has. An override that doesn't exist is skipped rather than failing every download.
+    pub fn locate(self, paths: &ToolPaths) -> Option<(PathBuf, ToolOrigin)> {
+        paths.get(self).filter(|p| p.is_file()).map(|p| (p.clone(), ToolOrigin::Override))
+            .or_else(|| Some(self.install_path()).filter(|p| p.is_file()).map(|p| (p, ToolOrigin::Managed)))
+            .or_else(|| find_on_path(self.binary_name()).map(|p| (p, ToolOrigin::Path)))
+    }
+
+    // Falls back to the bare name so a missing tool fails with a clear "not found" when run.
+    pub fn resolve(self, paths: &ToolPaths) -> PathBuf {
+        self.locate(paths).map(|(p, _)| p).unwrap_or_else(|| PathBuf::from(self.binary_name()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolOrigin { Override, Managed, Path }
+impl ToolOrigin {
+    pub fn as_str(&self) -> &'static str { match self { ToolOrigin::Override => "custom path", ToolOrigin::Managed => "managed", ToolOrigin::Path => "from PATH" } }
+}
+
+// User-chosen binaries that take precedence over the managed copies.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolPaths { pub ytdlp: Option<PathBuf>, pub ffmpeg: Option<PathBuf> }
+impl ToolPaths {
+    pub fn get(&self, tool: Tool) -> Option<&PathBuf> { match tool { Tool::YtDlp => self.ytdlp.as_ref(), Tool::Ffmpeg => self.ffmpeg.as_ref() } }
+    pub fn set(&mut self, tool: Tool, path: Option<PathBuf>) { match tool { Tool::YtDlp => self.ytdlp = path, Tool::Ffmpeg => self.ffmpeg = path } }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum UpdateChannel { #[default] Stable, Nightly, Pinned }
+impl UpdateChannel {
+    pub const ALL: [UpdateChannel; 3] = [UpdateChannel::Stable, UpdateChannel::Nightly, UpdateChannel::Pinned];
+    pub fn as_str(&self) -> &'static str { match self { UpdateChannel::Stable => "Stable", UpdateChannel::Nightly => "Nightly", UpdateChannel::Pinned => "Pinned version" } }
+}
+impl std::fmt::Display for UpdateChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.as_str()) }
 }
 
-pub async fn auto_update_task() -> Result<String, String> {
-    let y = check_and_update_ytdlp().await;
-    let f = check_and_update_ffmpeg().await;
+// `pinned_version` is kept when switching away from Pinned so switching back doesn't lose it.
+// `skip_next_update` is set by a rollback that couldn't pin and used up by the next update check.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolChannel { pub channel: UpdateChannel, pub pinned_version: String, pub skip_next_update: bool }
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolChannels { pub ytdlp: ToolChannel, pub ffmpeg: ToolChannel }
+impl ToolChannels {
+    pub fn get(&self, tool: Tool) -> &ToolChannel { match tool { Tool::YtDlp => &self.ytdlp, Tool::Ffmpeg => &self.ffmpeg } }
+    pub fn get_mut(&mut self, tool: Tool) -> &mut ToolChannel { match tool { Tool::YtDlp => &mut self.ytdlp, Tool::Ffmpeg => &mut self.ffmpeg } }
+}
+
+pub fn tools_dir() -> PathBuf {
+    // Tests install and back up binaries and mustn't touch the real ones
+    if cfg!(test) { return std::env::temp_dir().join(format!("yt-dlp-gui-test-{}", std::process::id())).join("tools"); }
+    dirs::data_dir().unwrap_or_else(|| std::env::current_dir().unwrap_or_default()).join("yt-dlp-gui").join("tools")
+}
+
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?).map(|dir| dir.join(name)).find(|p| p.is_file())
+}
+
+// Download locations for the updater. The defaults match the running platform; the config file can
+// point them anywhere, e.g. at a local server when testing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolSources {
+    pub ytdlp_download: String,
+    pub ffmpeg_download: String,
+    pub ytdlp_release_api: String,
+    pub ffmpeg_release_api: String,
+    // Published hashes for the downloads: a checksum list with one `<hash>  <file>` per line or a file
+    // holding just the hash. SHA-256 and MD5 are told apart by length. `None` installs unverified.
+    pub ytdlp_checksums: Option<String>,
+    pub ffmpeg_checksums: Option<String>,
+    // The other channels. `None` where the platform has no such builds.
+    pub ytdlp_nightly: Option<ChannelSource>,
+    pub ffmpeg_nightly: Option<ChannelSource>,
+    // Templates for a pinned version: `{version}` is replaced with the version asked for.
+    pub ytdlp_pinned: Option<ChannelSource>,
+    pub ffmpeg_pinned: Option<ChannelSource>,
+}
+
+// Where one channel's builds come from. `release_api` announces the latest version; pinned sources
+// have none since the version is already known.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelSource { pub download: String, pub release_api: Option<String>, pub checksums: Option<String> }
+
+impl Default for ToolSources {
+    fn default() -> Self {
+        let ytdlp = if cfg!(windows) { "yt-dlp.exe" }
+            else if cfg!(target_os = "macos") { "yt-dlp_macos" }
+            else if cfg!(target_arch = "aarch64") { "yt-dlp_linux_aarch64" }
+            else { "yt-dlp_linux" };
+        let ffmpeg = if cfg!(windows) { "https://www.gyan.dev/ffmpeg/builds/ffmpeg-release-essentials.zip" }
+            else if cfg!(target_os = "macos") { "https://evermeet.cx/ffmpeg/getrelease/zip" }
+            else if cfg!(target_arch = "aarch64") { "https://johnvansickle.com/ffmpeg/releases/ffmpeg-release-arm64-static.tar.xz" }
+            else { "https://johnvansickle.com/ffmpeg/releases/ffmpeg-release-amd64-static.tar.xz" };
+        let ffmpeg_pinned = if cfg!(windows) { "https://github.com/GyanD/codexffmpeg/releases/download/{version}/ffmpeg-{version}-essentials_build.zip" }
+            else if cfg!(target_os = "macos") { "https://evermeet.cx/ffmpeg/ffmpeg-{version}.zip" }
+            else if cfg!(target_arch = "aarch64") { "https://johnvansickle.com/ffmpeg/old-releases/ffmpeg-{version}-arm64-static.tar.xz" }
+            else { "https://johnvansickle.com/ffmpeg/old-releases/ffmpeg-{version}-amd64-static.tar.xz" };
+        ToolSources {
+            ytdlp_download: format!("https://github.com/yt-dlp/yt-dlp/releases/latest/download/{}", ytdlp),
+            ffmpeg_download: ffmpeg.to_string(),
+            ytdlp_release_api: "https://api.github.com/repos/yt-dlp/yt-dlp/releases/latest".to_string(),
+            // Each ffmpeg source announces its latest build differently; `latest_from_feed` reads all three
+            ffmpeg_release_api: if cfg!(windows) { "https://api.github.com/repos/GyanD/codexffmpeg/releases/latest" }
+                else if cfg!(target_os = "macos") { "https://evermeet.cx/ffmpeg/info/ffmpeg/release" }
+                else { "https://johnvansickle.com/ffmpeg/release-readme.txt" }.to_string(),
+            ytdlp_checksums: Some("https://github.com/yt-dlp/yt-dlp/releases/latest/download/SHA2-256SUMS".to_string()),
+            // gyan.dev publishes a .sha256 next to each build and johnvansickle a .md5; evermeet has only GPG signatures
+            ffmpeg_checksums: if cfg!(windows) { Some(format!("{}.sha256", ffmpeg)) }
+                else if cfg!(target_os = "macos") { None }
+                else { Some(format!("{}.md5", ffmpeg)) },
+            ytdlp_nightly: Some(ChannelSource {
+                download: format!("https://github.com/yt-dlp/yt-dlp-nightly-builds/releases/latest/download/{}", ytdlp),
+                release_api: Some("https://api.github.com/repos/yt-dlp/yt-dlp-nightly-builds/releases/latest".to_string()),
+                checksums: Some("https://github.com/yt-dlp/yt-dlp-nightly-builds/releases/latest/download/SHA2-256SUMS".to_string()),
+            }),
+            // Only evermeet announces the version of its snapshot builds, which the update check needs
+            ffmpeg_nightly: cfg!(target_os = "macos").then(|| ChannelSource {
+                download: "https://evermeet.cx/ffmpeg/get/zip".to_string(),
+                release_api: Some("https://evermeet.cx/ffmpeg/info/ffmpeg/snapshot".to_string()),
+                checksums: None,
+            }),
+            ytdlp_pinned: Some(ChannelSource {
+                download: format!("https://github.com/yt-dlp/yt-dlp/releases/download/{{version}}/{}", ytdlp),
+                release_api: None,
+                checksums: Some("https://github.com/yt-dlp/yt-dlp/releases/download/{version}/SHA2-256SUMS".to_string()),
+            }),
+            ffmpeg_pinned: Some(ChannelSource { download: ffmpeg_pinned.to_string(), release_api: None, checksums: None }),
+        }
+    }
+}
+
+impl ToolSources {
+    pub fn channel_source(&self, tool: Tool, channel: &ToolChannel) -> Result<ChannelSource, String> {
+        match channel.channel {
+            UpdateChannel::Stable => Ok(match tool {
+                Tool::YtDlp => ChannelSource { download: self.ytdlp_download.clone(), release_api: Some(self.ytdlp_release_api.clone()), checksums: self.ytdlp_checksums.clone() },
+                Tool::Ffmpeg => ChannelSource { download: self.ffmpeg_download.clone(), release_api: Some(self.ffmpeg_release_api.clone()), checksums: self.ffmpeg_checksums.clone() },
+            }),
+            UpdateChannel::Nightly => match tool { Tool::YtDlp => &self.ytdlp_nightly, Tool::Ffmpeg => &self.ffmpeg_nightly }.clone()
+                .ok_or_else(|| "no nightly builds for this platform".to_string()),
+            UpdateChannel::Pinned => {
+                let version = channel.pinned_version.trim();
+                if version.is_empty() { return Err("no version pinned".into()); }
+                let template = match tool { Tool::YtDlp => &self.ytdlp_pinned, Tool::Ffmpeg => &self.ffmpeg_pinned }.as_ref()
+                    .ok_or_else(|| "no source for pinned versions".to_string())?;
+                Ok(ChannelSource {
+                    download: template.download.replace("{version}", version),
+                    release_api: None,
+                    checksums: template.checksums.as_ref().map(|c| c.replace("{version}", version)),
+                })
+            }
+        }
+    }
+
+    // Whether the Pinned channel can download `version` again: only releases are published under
+    // their version, not nightlies, snapshots or git builds.
+    pub fn can_pin(&self, tool: Tool, version: &str) -> bool {
+        match tool {
+            Tool::YtDlp => self.ytdlp_pinned.is_some() && YtDlpVersion::parse(version).is_some_and(|v| v.build.is_none()),
+            Tool::Ffmpeg => self.ffmpeg_pinned.is_some() && matches!(FfmpegVersion::parse(version), Some(FfmpegVersion::Release(_))),
+        }
+    }
+}
+
+// `allow_unverified` lets sources that publish no checksums install anyway.
+pub async fn auto_update_task(sources: ToolSources, paths: ToolPaths, channels: ToolChannels, keep_backups: usize, allow_unverified: bool) -> Result<String, String> {
+    let y = check_and_update(Tool::YtDlp, &sources, &paths, channels.get(Tool::YtDlp), keep_backups, allow_unverified).await;
+    let f = check_and_update(Tool::Ffmpeg, &sources, &paths, channels.get(Tool::Ffmpeg), keep_backups, allow_unverified).await;
     match (y, f) {
         (Ok(y), Ok(f)) => Ok(format!("yt-dlp: {}, FFmpeg: {}", y, f)),
         (Err(e), _) => Err(format!("yt-dlp error: {}", e)),
@@ -26,55 +213,464 @@ pub async fn auto_update_task() -> Result<String, String> {
     }
 }
 
-async fn check_and_update_ytdlp() -> Result<String, String> {
-    let local = if let Ok(o) = TokioCommand::new("yt-dlp").arg("--version").output().await {
-        String::from_utf8_lossy(&o.stdout).trim().to_string()
-    } else { "0".into() };
+async fn check_and_update(tool: Tool, sources: &ToolSources, paths: &ToolPaths, channel: &ToolChannel, keep_backups: usize, allow_unverified: bool) -> Result<String, String> {
+    // A binary the user picked is theirs to keep current
+    if paths.get(tool).is_some_and(|p| p.is_file()) { return Ok("custom path, not managed".into()); }
+    if channel.skip_next_update { return Ok("update skipped after rollback".into()); }
+    let source = sources.channel_source(tool, channel)?;
+    let local = match tool.locate(paths) { Some((path, _)) => version_output(tool, &path).await, None => None };
+    let target = match &source.release_api {
+        Some(api) => latest_version(api).await?,
+        None => channel.pinned_version.trim().to_string(),
+    };
+    let unrecognised = || format!("Unrecognised version '{}'", target);
+    let pinned = channel.channel == UpdateChannel::Pinned;
 
-    let client = reqwest::Client::new();
-    let resp = client.get("https://api.github.com/repos/yt-dlp/yt-dlp/releases/latest")
-        .header("User-Agent", "rust-updater")
-        .send().await.map_err(|e|e.to_string())?
-        .json::<GitHubRelease>().await.map_err(|e|e.to_string())?;
+    // The managed copy takes precedence over PATH, so installing one also replaces an outdated system binary
+    let (update, current) = match tool {
+        Tool::YtDlp => {
+            let remote = YtDlpVersion::parse(&target).ok_or_else(unrecognised)?;
+            if pinned && remote.build.is_some() { return Err("only stable releases can be pinned".into()); }
+            let local = local.as_deref().and_then(YtDlpVersion::parse);
+            // A nightly and a stable build are never "the same", so switching channel always installs, even a lower version
+            let update = match local {
+                None => true,
+                Some(l) if pinned => l != remote,
+                Some(l) => remote > l || l.build.is_some() != remote.build.is_some(),
+            };
+            (update, local.map(|l| l.to_string()))
+        }
+        Tool::Ffmpeg => {
+            let remote = FfmpegVersion::parse(&target).ok_or_else(unrecognised)?;
+            match local.as_deref().and_then(FfmpegVersion::from_output) {
+                None => (true, None),
+                Some(l) if pinned => (remote.compare(&l) != Some(Ordering::Equal), Some(l.to_string())),
+                Some(l) => match remote.compare(&l) {
+                    Some(order) => (order == Ordering::Greater, Some(l.to_string())),
+                    // e.g. a git snapshot installed by hand against a release feed; leave it alone
+                    None => return Ok(format!("{} (latest {} is a different kind of build)", l, remote)),
+                },
+            }
+        }
+    };
+    if update {
+        let check = install_tool(tool, &source, current.as_deref().unwrap_or("unknown"), keep_backups, allow_unverified).await?;
+        Ok(format!("Updated {} to {} ({})", current.as_deref().unwrap_or("nothing"), target, check))
+    } else {
+        Ok(format!("{} up to date", current.unwrap_or_default()))
+    }
+}
+
+// `--version` output of the binary at `path`, or `None` if it can't be run.
+pub async fn version_output(tool: Tool, path: &Path) -> Option<String> {
+    let flag = match tool { Tool::YtDlp => "--version", Tool::Ffmpeg => "-version" };
+    let mut cmd = TokioCommand::new(path);
+    cmd.arg(flag);
+    #[cfg(windows)] cmd.creation_flags(0x08000000);
+    let out = cmd.output().await.ok()?;
+    Some(String::from_utf8_lossy(&out.stdout).trim().to_string()).filter(|s| !s.is_empty())
+}
 
-    if local != resp.tag_name { download_ytdlp_task().await?; Ok(format!("Updated to {}", resp.tag_name)) } 
-    else { Ok("Up to date".into()) }
+// The short form shown in the UI; builds we can't parse show their first line instead.
+pub fn display_version(tool: Tool, output: &str) -> String {
+    let parsed = match tool {
+        Tool::YtDlp => YtDlpVersion::parse(output).map(|v| v.to_string()),
+        Tool::Ffmpeg => FfmpegVersion::from_output(output).map(|v| v.to_string()),
+    };
+    parsed.unwrap_or_else(|| output.lines().next().unwrap_or_default().to_string())
+}
+
+// What the UI shows per tool: where the binary in use is, its version, whether a custom path points
+// nowhere and the backups that can be rolled back to.
+#[derive(Debug, Clone, Default)]
+pub struct ToolInfo { pub location: Option<(PathBuf, ToolOrigin)>, pub version: Option<String>, pub override_missing: bool, pub backups: Vec<Backup> }
+
+pub async fn tool_info_task(paths: ToolPaths) -> Vec<(Tool, ToolInfo)> {
+    let mut info = Vec::new();
+    for tool in Tool::ALL {
+        let location = tool.locate(&paths);
+        let version = match &location {
+            Some((path, _)) => version_output(tool, path).await.map(|out| display_version(tool, &out)),
+            None => None,
+        };
+        let override_missing = paths.get(tool).is_some_and(|p| !p.is_file());
+        info.push((tool, ToolInfo { location, version, override_missing, backups: backups::list(tool) }));
+    }
+    info
 }
 
-async fn check_and_update_ffmpeg() -> Result<String, String> {
-    let local = if let Ok(o) = TokioCommand::new("ffmpeg").arg("-version").output().await {
-        let t = String::from_utf8_lossy(&o.stdout);
-        FFMPEG_DATE_RE.captures(&t).and_then(|c| c.get(1)).map(|m| m.as_str().to_string()).unwrap_or("0".into())
-    } else { "0".into() };
+// Returns the version rolled back to.
+pub async fn rollback_task(tool: Tool, backup: PathBuf, keep_backups: usize) -> Result<String, String> {
+    let current = version_output(tool, &tool.install_path()).await.map(|out| display_version(tool, &out)).unwrap_or("unknown".into());
+    tokio::task::spawn_blocking(move || backups::restore(tool, &backup, &current, keep_backups)).await.map_err(|e| e.to_string())?
+}
 
-    let client = reqwest::Client::new();
-    let resp = client.get("https://api.github.com/repos/GyanD/codexffmpeg/releases/latest")
+async fn latest_version(url: &str) -> Result<String, String> {
+    let body = reqwest::Client::new().get(url)
         .header("User-Agent", "rust-updater")
         .send().await.map_err(|e|e.to_string())?
-        .json::<GitHubRelease>().await.map_err(|e|e.to_string())?;
+        .text().await.map_err(|e|e.to_string())?;
+    latest_from_feed(&body).ok_or_else(|| format!("No version found at {}", url))
+}
+
+// Downloads the tool, checks it against the published hash, pulls the binary out if it came in an
+// archive and swaps it in. Nothing touches the installed copy until every step has passed, so a bad
+// download leaves the old binary working, and the one being replaced is kept as a backup labelled
+// `current_version`. Returns how the download was verified.
+pub async fn install_tool(tool: Tool, source: &ChannelSource, current_version: &str, keep_backups: usize, allow_unverified: bool) -> Result<String, String> {
+    if source.checksums.is_none() && !allow_unverified { return Err(unverified_refused(&source.download)); }
+    let url = source.download.clone();
+    let bytes = fetch_bytes(&url).await?;
+    let sums = match source.checksums.as_deref() {
+        Some(sums_url) => Some((sums_url.to_string(), String::from_utf8_lossy(&fetch_bytes(sums_url).await?).to_string())),
+        None => None,
+    };
+    let current_version = current_version.to_string();
+    // Hashing, unpacking and writing a ~100 MB download would stall the executor the UI runs on
+    tokio::task::spawn_blocking(move || install_bytes(tool, &bytes, &url, sums, allow_unverified, &current_version, keep_backups))
+        .await.map_err(|e| e.to_string())?
+}
+
+// The part of `install_tool` that runs once everything is downloaded.
+fn install_bytes(tool: Tool, bytes: &[u8], url: &str, sums: Option<(String, String)>, allow_unverified: bool, current_version: &str, keep_backups: usize) -> Result<String, String> {
+    let verified = verify_checksum(bytes, url, sums, allow_unverified)?;
+    let binary = extract_binary(bytes, tool.binary_name())?;
+    write_atomic_with(&tool.install_path(), &binary, |tmp| { set_executable(tmp)?; backups::save(tool, current_version, keep_backups) })?;
+    Ok(verified)
+}
+
+fn unverified_refused(url: &str) -> String {
+    format!("No checksum is published for {}; allow unverified tool downloads to install it anyway", url)
+}
+
+// `sums` is the checksum source's URL and content. Returns how the download was verified.
+fn verify_checksum(bytes: &[u8], url: &str, sums: Option<(String, String)>, allow_unverified: bool) -> Result<String, String> {
+    let Some((sums_url, sums)) = sums else {
+        return if allow_unverified { Ok("not verified, no checksum published".to_string()) } else { Err(unverified_refused(url)) };
+    };
+    let file_name = url.trim_end_matches('/').rsplit('/').next().unwrap_or(url);
+    let expected = expected_hash(&sums, file_name).ok_or_else(|| format!("No checksum for {} in {}", file_name, sums_url))?;
+    let (algo, actual) = match expected.len() {
+        64 => ("sha256", format!("{:x}", Sha256::digest(bytes))),
+        32 => ("md5", format!("{:x}", Md5::digest(bytes))),
+        n => return Err(format!("Unrecognised checksum of length {} in {}", n, sums_url)),
+    };
+    if !actual.eq_ignore_ascii_case(&expected) {
+        return Err(format!("{} checksum mismatch for {}: expected {}, got {}", algo, file_name, expected, actual));
+    }
+    Ok(format!("{} verified", algo))
+}
 
-    if local != resp.tag_name { download_ffmpeg_task().await?; Ok(format!("Updated to {}", resp.tag_name)) } 
-    else { Ok("Up to date".into()) }
+async fn fetch_bytes(url: &str) -> Result<bytes::Bytes, String> {
+    let resp = reqwest::get(url).await.map_err(|e|e.to_string())?;
+    if !resp.status().is_success() { return Err(format!("{} returned HTTP {}", url, resp.status().as_u16())); }
+    resp.bytes().await.map_err(|e|e.to_string())
+}
+
+// Finds the hash for `file_name`: a `<hash>  <name>` line (`*name` in binary mode) or, for
+// single-file hash files, a line holding only the hash.
+fn expected_hash(sums: &str, file_name: &str) -> Option<String> {
+    let is_hash = |s: &str| (s.len() == 64 || s.len() == 32) && s.chars().all(|c| c.is_ascii_hexdigit());
+    let mut lone = None;
+    for line in sums.lines() {
+        let mut parts = line.split_whitespace();
+        let (Some(hash), name) = (parts.next(), parts.next()) else { continue };
+        if !is_hash(hash) { continue; }
+        match name.map(|n| n.trim_start_matches('*')) {
+            Some(n) if n == file_name || n.rsplit('/').next() == Some(file_name) => return Some(hash.to_string()),
+            None => lone = lone.or(Some(hash.to_string())),
+            _ => {}
+        }
+    }
+    lone
 }
 
-pub async fn download_ytdlp_task() -> Result<String, String> {
-    let url = "https://github.com/yt-dlp/yt-dlp/releases/latest/download/yt-dlp.exe";
-    let b = reqwest::get(url).await.map_err(|e|e.to_string())?.bytes().await.map_err(|e|e.to_string())?;
-    tokio::fs::write("yt-dlp.exe", b).await.map_err(|e|e.to_string())?;
-    Ok("Installed".into())
+// Archives are recognised by their magic bytes rather than the URL, which doesn't always say
+// (evermeet's download link has no extension).
+fn extract_binary(bytes: &[u8], name: &str) -> Result<Vec<u8>, String> {
+    if bytes.starts_with(b"PK\x03\x04") {
+        let mut a = zip::ZipArchive::new(std::io::Cursor::new(bytes)).map_err(|e|e.to_string())?;
+        for i in 0..a.len() {
+            let mut f = a.by_index(i).map_err(|e|e.to_string())?;
+            if f.enclosed_name().and_then(|p| p.file_name()).is_some_and(|n| n == name) {
+                let mut out = Vec::new();
+                std::io::copy(&mut f, &mut out).map_err(|e|e.to_string())?;
+                return Ok(out);
+            }
+        }
+        return Err(format!("{} not found in zip", name));
+    }
+    if bytes.starts_with(b"\xFD7zXZ\x00") {
+        let mut tar_bytes = Vec::new();
+        lzma_rs::xz_decompress(&mut std::io::Cursor::new(bytes), &mut tar_bytes).map_err(|e| format!("xz: {}", e))?;
+        let mut archive = tar::Archive::new(std::io::Cursor::new(tar_bytes));
+        for entry in archive.entries().map_err(|e|e.to_string())? {
+            let mut entry = entry.map_err(|e|e.to_string())?;
+            let matches = entry.path().ok().and_then(|p| p.file_name().map(|n| n == name)).unwrap_or(false);
+            if matches && entry.header().entry_type().is_file() {
+                let mut out = Vec::new();
+                std::io::copy(&mut entry, &mut out).map_err(|e|e.to_string())?;
+                return Ok(out);
+            }
+        }
+        return Err(format!("{} not found in tar.xz", name));
+    }
+    // Error pages come back with a 200 from some mirrors
+    if bytes.trim_ascii_start().starts_with(b"<") { return Err("Got a web page instead of a binary".into()); }
+    Ok(bytes.to_vec())
+}
+
+pub fn set_executable(path: &Path) -> Result<(), String> {
+    #[cfg(unix)] {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).map_err(|e|e.to_string())?;
+    }
+    #[cfg(not(unix))] let _ = path;
+    Ok(())
 }
 
-pub async fn download_ffmpeg_task() -> Result<String, String> {
-    let url = "https://www.gyan.dev/ffmpeg/builds/ffmpeg-release-essentials.zip";
-    let b = reqwest::get(url).await.map_err(|e|e.to_string())?.bytes().await.map_err(|e|e.to_string())?;
-    let mut a = zip::ZipArchive::new(std::io::Cursor::new(b)).map_err(|e|e.to_string())?;
-    for i in 0..a.len() {
-        let mut f = a.by_index(i).map_err(|e|e.to_string())?;
-        if f.enclosed_name().unwrap_or(std::path::Path::new("")).file_name().unwrap_or_default() == "ffmpeg.exe" {
-            let mut o = std::fs::File::create("ffmpeg.exe").map_err(|e|e.to_string())?;
-            std::io::copy(&mut f, &mut o).map_err(|e|e.to_string())?;
-            return Ok("Installed".into());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::Arc;
+    use once_cell::sync::Lazy;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Every test that installs shares the one test tools folder
+    static INSTALL_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+    const SUMS_URL: &str = "https://example.com/SHA2-256SUMS";
+
+    // Starts from an empty tools folder, with `old` as the installed binary if given.
+    fn fresh(tool: Tool, old: Option<&[u8]>) {
+        let _ = std::fs::remove_dir_all(tools_dir());
+        if let Some(old) = old {
+            std::fs::create_dir_all(tools_dir()).unwrap();
+            std::fs::write(tool.install_path(), old).unwrap();
         }
     }
-    Err("Not in zip".into())
-}
\ No newline at end of file
+
+    fn installed(tool: Tool) -> Option<Vec<u8>> { std::fs::read(tool.install_path()).ok() }
+
+    // A stand-in for the release servers: answers a GET for each path with its body and anything else
+    // with a 404. Returns the base URL.
+    async fn serve(routes: Vec<(String, Vec<u8>)>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let routes = Arc::new(routes);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let routes = routes.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match socket.read(&mut buf).await { Ok(0) | Err(_) => return, Ok(n) => request.extend_from_slice(&buf[..n]) }
+                    }
+                    let path = String::from_utf8_lossy(&request).split_whitespace().nth(1).unwrap_or_default().to_string();
+                    let (status, body) = match routes.iter().find(|(p, _)| *p == path) {
+                        Some((_, body)) => ("200 OK", body.clone()),
+                        None => ("404 Not Found", b"Not Found".to_vec()),
+                    };
+                    let head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len());
+                    let _ = socket.write_all(head.as_bytes()).await;
+                    let _ = socket.write_all(&body).await;
+                });
+            }
+        });
+        base
+    }
+
+    fn route(path: &str, body: impl Into<Vec<u8>>) -> (String, Vec<u8>) { (path.to_string(), body.into()) }
+
+    // Sources pointing every yt-dlp channel at `base`.
+    fn local_sources(base: &str) -> ToolSources {
+        ToolSources {
+            ytdlp_download: format!("{}/latest/yt-dlp_linux", base),
+            ytdlp_release_api: format!("{}/releases/latest", base),
+            ytdlp_checksums: Some(format!("{}/latest/SHA2-256SUMS", base)),
+            ytdlp_pinned: Some(ChannelSource {
+                download: format!("{}/download/{{version}}/yt-dlp_linux", base),
+                release_api: None,
+                checksums: Some(format!("{}/download/{{version}}/SHA2-256SUMS", base)),
+            }),
+            ..ToolSources::default()
+        }
+    }
+
+    fn sha256(bytes: &[u8]) -> String { format!("{:x}", Sha256::digest(bytes)) }
+    fn md5(bytes: &[u8]) -> String { format!("{:x}", Md5::digest(bytes)) }
+
+    fn zip_with(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, data) in files {
+            zip.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn tar_xz_with(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(Vec::new());
+        for (name, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            tar.append_data(&mut header, name, *data).unwrap();
+        }
+        let mut xz = Vec::new();
+        lzma_rs::xz_compress(&mut std::io::Cursor::new(tar.into_inner().unwrap()), &mut xz).unwrap();
+        xz
+    }
+
+    #[tokio::test]
+    async fn pinned_install_from_a_local_server() {
+        let _lock = INSTALL_LOCK.lock().await;
+        let bin = b"\x7fELF yt-dlp 2024.07.25".to_vec();
+        let base = serve(vec![
+            route("/download/2024.07.25/yt-dlp_linux", bin.clone()),
+            route("/download/2024.07.25/SHA2-256SUMS", format!("{}  yt-dlp.exe\n{}  yt-dlp_linux\n", "0".repeat(64), sha256(&bin))),
+        ]).await;
+        let sources = local_sources(&base);
+        let tool = Tool::YtDlp;
+        fresh(tool, Some(b"old yt-dlp"));
+
+        let pinned = ToolChannel { channel: UpdateChannel::Pinned, pinned_version: "2024.07.25".into(), skip_next_update: false };
+        let source = sources.channel_source(tool, &pinned).unwrap();
+        assert_eq!(install_tool(tool, &source, "2024.08.06", 1, false).await.unwrap(), "sha256 verified");
+        assert_eq!(installed(tool), Some(bin));
+        assert_eq!(backups::list(tool).iter().map(|b| b.version.as_str()).collect::<Vec<_>>(), ["2024.08.06"]);
+
+        // A version the server doesn't have fails on the download and changes nothing
+        let missing = ToolChannel { pinned_version: "2023.01.01".into(), ..pinned };
+        let err = install_tool(tool, &sources.channel_source(tool, &missing).unwrap(), "2024.07.25", 1, false).await.unwrap_err();
+        assert!(err.ends_with("returned HTTP 404"), "{}", err);
+        assert_eq!(installed(tool).as_deref(), Some(&b"\x7fELF yt-dlp 2024.07.25"[..]));
+    }
+
+    // Runs the served "binaries", which are shell scripts printing their version
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn update_check_against_a_local_server() {
+        let _lock = INSTALL_LOCK.lock().await;
+        let new = b"#!/bin/sh\necho 2024.08.06\n".to_vec();
+        let base = serve(vec![
+            route("/releases/latest", r#"{"tag_name":"2024.08.06","name":"yt-dlp 2024.08.06"}"#),
+            route("/latest/yt-dlp_linux", new.clone()),
+            route("/latest/SHA2-256SUMS", format!("{}  yt-dlp_linux\n", sha256(&new))),
+        ]).await;
+        let sources = local_sources(&base);
+        let tool = Tool::YtDlp;
+        fresh(tool, Some(b"#!/bin/sh\necho 2024.07.25\n"));
+        set_executable(&tool.install_path()).unwrap();
+        let stable = ToolChannel::default();
+
+        let result = check_and_update(tool, &sources, &ToolPaths::default(), &stable, 3, false).await.unwrap();
+        assert_eq!(result, "Updated 2024.07.25 to 2024.08.06 (sha256 verified)");
+        assert_eq!(installed(tool), Some(new));
+        assert_eq!(backups::list(tool).iter().map(|b| b.version.as_str()).collect::<Vec<_>>(), ["2024.07.25"]);
+
+        assert_eq!(check_and_update(tool, &sources, &ToolPaths::default(), &stable, 3, false).await.unwrap(), "2024.08.06 up to date");
+        let skipped = ToolChannel { skip_next_update: true, ..stable };
+        assert_eq!(check_and_update(tool, &sources, &ToolPaths::default(), &skipped, 3, false).await.unwrap(), "update skipped after rollback");
+    }
+
+    #[test]
+    fn finds_the_hash_for_the_download() {
+        let a = "a".repeat(64);
+        let b = "b".repeat(64);
+        // yt-dlp's SHA2-256SUMS: one line per release file
+        let sums = format!("{}  yt-dlp\n{}  yt-dlp_linux\n{}  yt-dlp.exe\n", "0".repeat(64), a, b);
+        assert_eq!(expected_hash(&sums, "yt-dlp_linux"), Some(a.clone()));
+        assert_eq!(expected_hash(&sums, "yt-dlp.exe"), Some(b.clone()));
+        // Binary mode marks the name with a `*`; some lists carry a path
+        assert_eq!(expected_hash(&format!("{} *ffmpeg-release-amd64-static.tar.xz\n", "c".repeat(32)), "ffmpeg-release-amd64-static.tar.xz"), Some("c".repeat(32)));
+        assert_eq!(expected_hash(&format!("{}  builds/ffmpeg.zip\n", a), "ffmpeg.zip"), Some(a.clone()));
+        // gyan.dev's .sha256 holds just the hash
+        assert_eq!(expected_hash(&format!("{}\n", a), "ffmpeg-release-essentials.zip"), Some(a.clone()));
+        assert_eq!(expected_hash(&sums, "yt-dlp_macos"), None);
+        assert_eq!(expected_hash("<html>404</html>", "yt-dlp"), None);
+        let missing = verify_checksum(b"bin", "https://example.com/yt-dlp_macos", Some((SUMS_URL.into(), sums)), false).unwrap_err();
+        assert!(missing.contains("No checksum for yt-dlp_macos"), "{}", missing);
+    }
+
+    #[test]
+    fn matching_checksums_verify() {
+        let bin = b"\x7fELF new yt-dlp";
+        let url = "https://example.com/yt-dlp_linux";
+        assert_eq!(verify_checksum(bin, url, Some((SUMS_URL.into(), format!("{}  yt-dlp_linux", sha256(bin)))), false).unwrap(), "sha256 verified");
+        assert_eq!(verify_checksum(bin, url, Some((SUMS_URL.into(), md5(bin).to_uppercase())), false).unwrap(), "md5 verified");
+    }
+
+    #[test]
+    fn mismatches_leave_the_installed_binary_alone() {
+        let _lock = INSTALL_LOCK.blocking_lock();
+        let tool = Tool::YtDlp;
+        let bin = b"\x7fELF tampered yt-dlp";
+        for sums in [format!("{}  yt-dlp_linux", sha256(b"the real one")), format!("{}  yt-dlp_linux", md5(b"the real one"))] {
+            fresh(tool, Some(b"old yt-dlp"));
+            let err = install_bytes(tool, bin, "https://example.com/yt-dlp_linux", Some((SUMS_URL.into(), sums)), false, "2024.07.25", 3).unwrap_err();
+            assert!(err.contains("checksum mismatch"), "{}", err);
+            assert_eq!(installed(tool).as_deref(), Some(&b"old yt-dlp"[..]));
+            assert!(backups::list(tool).is_empty());
+        }
+    }
+
+    #[test]
+    fn unverified_installs_need_opting_in() {
+        let _lock = INSTALL_LOCK.blocking_lock();
+        let tool = Tool::Ffmpeg;
+        fresh(tool, Some(b"old ffmpeg"));
+        let err = install_bytes(tool, b"new ffmpeg", "https://evermeet.cx/ffmpeg/getrelease/zip", None, false, "7.0.2", 3).unwrap_err();
+        assert!(err.contains("No checksum is published"), "{}", err);
+        assert_eq!(installed(tool).as_deref(), Some(&b"old ffmpeg"[..]));
+
+        assert_eq!(install_bytes(tool, b"new ffmpeg", "https://evermeet.cx/ffmpeg/getrelease/zip", None, true, "7.0.2", 3).unwrap(), "not verified, no checksum published");
+        assert_eq!(installed(tool).as_deref(), Some(&b"new ffmpeg"[..]));
+        assert_eq!(backups::list(tool).iter().map(|b| b.version.as_str()).collect::<Vec<_>>(), ["7.0.2"]);
+    }
+
+    #[test]
+    fn binaries_come_out_of_archives() {
+        let name = Tool::Ffmpeg.binary_name();
+        let zip = zip_with(&[("ffmpeg-7.0.2-essentials_build/README.txt", b"readme"), (&format!("ffmpeg-7.0.2-essentials_build/bin/{}", name), b"ffmpeg from zip")]);
+        assert_eq!(extract_binary(&zip, name).unwrap(), b"ffmpeg from zip");
+        assert!(extract_binary(&zip, "ffprobe-nope").unwrap_err().contains("not found in zip"));
+
+        let xz = tar_xz_with(&[("ffmpeg-7.0.2-amd64-static/GPLv3.txt", b"license"), (&format!("ffmpeg-7.0.2-amd64-static/{}", name), b"ffmpeg from tar.xz")]);
+        assert_eq!(extract_binary(&xz, name).unwrap(), b"ffmpeg from tar.xz");
+        assert!(extract_binary(&xz, "ffprobe-nope").unwrap_err().contains("not found in tar.xz"));
+
+        // Single-file downloads such as yt-dlp pass straight through
+        assert_eq!(extract_binary(b"\x7fELF yt-dlp", "yt-dlp").unwrap(), b"\x7fELF yt-dlp");
+    }
+
+    #[test]
+    fn error_pages_are_not_installed() {
+        let _lock = INSTALL_LOCK.blocking_lock();
+        let page = b"\r\n<!DOCTYPE html>\n<html><head><title>503 Service Unavailable</title></head></html>";
+        assert_eq!(extract_binary(page, "yt-dlp").unwrap_err(), "Got a web page instead of a binary");
+        // A matching hash for a page doesn't make it a binary either
+        let tool = Tool::YtDlp;
+        fresh(tool, Some(b"old yt-dlp"));
+        let sums = format!("{}  yt-dlp_linux", sha256(page));
+        assert!(install_bytes(tool, page, "https://example.com/yt-dlp_linux", Some((SUMS_URL.into(), sums)), false, "2024.07.25", 3).is_err());
+        assert_eq!(installed(tool).as_deref(), Some(&b"old yt-dlp"[..]));
+    }
+
+    #[test]
+    fn only_releases_can_be_pinned() {
+        let sources = ToolSources::default();
+        assert!(sources.can_pin(Tool::YtDlp, "2024.08.06"));
+        assert!(!sources.can_pin(Tool::YtDlp, "2024.08.07.232751"));
+        assert!(!sources.can_pin(Tool::YtDlp, "unknown"));
+        assert!(sources.can_pin(Tool::Ffmpeg, "7.0.2"));
+        assert!(!sources.can_pin(Tool::Ffmpeg, "N-116466"));
+        assert!(!sources.can_pin(Tool::Ffmpeg, "2024-08-04"));
+        assert!(!sources.can_pin(Tool::Ffmpeg, "unknown"));
+        let no_template = ToolSources { ffmpeg_pinned: None, ..ToolSources::default() };
+        assert!(!no_template.can_pin(Tool::Ffmpeg, "7.0.2"));
+    }
+}
@@ -1,10 +1,21 @@
+use std::collections::VecDeque;
 use std::path::PathBuf;
-use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use crate::formats::FormatInfo;
+use crate::errors::DownloadError;
+use crate::retry::RetryClass;
+use crate::proxy::CheckOutcome;
+use crate::tools::{Tool, ToolInfo, UpdateChannel};
+use crate::progress::Progress;
+use crate::logic::{DownloadRun, Stopped, WrittenFiles};
+use crate::quality::{QualityPreset, ResolutionCap, FpsCap, VideoCodecPref, AudioCodecPref, DynamicRangePref};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum AppTab { #[default] Dashboard, Audio, Video, Advanced }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum ProxyProtocol { 
     #[default] 
     Socks5, 
@@ -27,7 +38,7 @@ impl std::fmt::Display for ProxyProtocol {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.as_str()) }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum AudioFormat { #[default] None, Mp3, Aac, M4a, Wav, Flac, Opus }
 impl AudioFormat {
     pub const ALL: [AudioFormat; 7] = [AudioFormat::None, AudioFormat::Mp3, AudioFormat::Aac, AudioFormat::M4a, AudioFormat::Wav, AudioFormat::Flac, AudioFormat::Opus];
@@ -35,7 +46,7 @@ impl AudioFormat {
 }
 impl std::fmt::Display for AudioFormat { fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.as_str()) } }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum Container { #[default] Mp4, Mkv, Webm }
 impl Container {
     pub const ALL: [Container; 3] = [Container::Mp4, Container::Mkv, Container::Webm];
@@ -43,7 +54,7 @@ impl Container {
 }
 impl std::fmt::Display for Container { fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.as_str()) } }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum VideoType {
     #[default]
     Normal,
@@ -59,12 +70,19 @@ impl VideoType {
             VideoType::ThreeD => "Prefer 3D",
         }
     }
+    pub fn sort_marker(self) -> Option<&'static str> {
+        match self {
+            VideoType::Normal => None,
+            VideoType::VR360 => Some("vr"),
+            VideoType::ThreeD => Some("3d"),
+        }
+    }
 }
 impl std::fmt::Display for VideoType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.as_str()) }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum FilenameTemplate {
     #[default]
     Default, 
@@ -82,7 +100,7 @@ impl FilenameTemplate {
             FilenameTemplate::Numbered => "Playlist Index - Title",
         }
     }
-    pub fn to_cmd_arg(&self) -> Option<String> {
+    pub fn to_cmd_arg(self) -> Option<String> {
         match self {
             FilenameTemplate::Default => None,
             FilenameTemplate::Clean => Some("%(title)s.%(ext)s".to_string()),
@@ -95,7 +113,8 @@ impl std::fmt::Display for FilenameTemplate {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.as_str()) }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AdvOptions {
     pub audio_fmt: AudioFormat,
     pub container: Container,
@@ -109,9 +128,30 @@ pub struct AdvOptions {
     pub playlist_items: String,
     pub rate_limit: String,
     pub custom_args: String,
+    pub quality: QualityPreset,
+}
+impl Default for AdvOptions {
+    fn default() -> Self {
+        AdvOptions {
+            audio_fmt: AudioFormat::None,
+            container: Container::Mp4,
+            video_type: VideoType::Normal,
+            embed_subs: false,
+            sub_langs: "all".to_string(),
+            embed_meta: true,
+            embed_thumb: true,
+            filename_style: FilenameTemplate::Default,
+            sponsorblock: false,
+            playlist_items: String::new(),
+            rate_limit: String::new(),
+            custom_args: String::new(),
+            quality: QualityPreset::default(),
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DownloadItem {
     pub id: usize,
     pub url: String,
@@ -121,14 +161,43 @@ pub struct DownloadItem {
     pub speed: String,
     pub total_size: String,
     pub assigned_proxy: Option<String>,
+    // The proxy list line `assigned_proxy` came from, for health tracking
+    pub proxy_entry: Option<String>,
     pub options: AdvOptions,
+    pub video_format: Option<String>,
+    pub audio_format: Option<String>,
+    #[serde(skip)]
+    pub live: Progress,
+    // Failed runs so far, so a pause and resume doesn't use one up; reset by a manual retry
+    pub attempts: u32,
+    // Unix time of the scheduled automatic retry while the item sits in Failed
+    pub next_retry: Option<u64>,
+    // Warnings from the last run (format fallbacks and the like); they didn't stop the download
+    pub notices: Vec<String>,
+    // Files of earlier, stopped runs that a resume continues and a cancel may delete
+    pub kept_files: WrittenFiles,
+    // Set while a pause or cancel waits for yt-dlp to exit
+    #[serde(skip)]
+    pub stopping: bool,
+    // Set when the queue starts the item; the download subscription runs from it
+    #[serde(skip)]
+    pub run: Option<Arc<DownloadRun>>,
+    #[serde(skip)]
+    pub log: VecDeque<String>,
+    #[serde(skip)]
+    pub started: Option<Instant>,
+}
+impl DownloadItem {
+    pub fn new(id: usize, url: String, title: String, options: AdvOptions) -> Self {
+        DownloadItem { id, url, title, speed: "-".into(), total_size: "-".into(), options, ..Default::default() }
+    }
 }
 
-#[derive(Debug, Clone)]
-pub enum DownloadStatus { Queued, Downloading, Finished, Failed(String), Cancelled }
+// Distinct warnings kept per item; yt-dlp can repeat the same one for every fragment
+pub const MAX_NOTICES: usize = 10;
 
-#[derive(Deserialize, Debug)]
-pub struct GitHubRelease { pub tag_name: String }
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub enum DownloadStatus { #[default] Queued, Downloading, Paused, Finished, Failed(DownloadError), Cancelled }
 
 #[derive(Debug, Clone)]
 pub enum Message {
@@ -140,6 +209,22 @@ pub enum Message {
     AnalysisFinished(Result<serde_json::Value, String>),
     LiveDecision(bool),
     CloseModal,
+
+    // Playlist selection
+    PlaylistEntryToggled(usize, bool),
+    PlaylistSelectAll,
+    PlaylistSelectNone,
+    PlaylistFilterChanged(String),
+    PlaylistRangeChanged(String),
+    PlaylistApplyRange,
+    PlaylistConfirm,
+
+    // Format browser
+    OpenFormatBrowser(usize),
+    FormatsLoaded(String, Result<Vec<FormatInfo>, String>),
+    PickVideoFormat(Option<String>),
+    PickAudioFormat(Option<String>),
+    ApplyFormats,
     
     // Settings
     AudioFmtChanged(AudioFormat),
@@ -150,27 +235,51 @@ pub enum Message {
     ToggleEmbedMeta(bool),
     ToggleEmbedThumb(bool),
     FilenameStyleChanged(FilenameTemplate), 
+    MaxResolutionChanged(ResolutionCap),
+    MaxFpsChanged(FpsCap),
+    VideoCodecChanged(VideoCodecPref),
+    AudioCodecChanged(AudioCodecPref),
+    DynamicRangeChanged(DynamicRangePref),
+    MaxFilesizeChanged(String),
     ToggleSponsorBlock(bool),
     PlaylistItemsChanged(String),
     RateLimitChanged(String),
     CustomArgsChanged(String),
     ProxyProtocolChanged(ProxyProtocol),
     MaxConcurrentChanged(String),
+    ToggleDeletePartials(bool),
+    ToggleRetry(bool),
+    RetryAttemptsChanged(u32),
+    RetryBaseDelayChanged(u64),
+    RetryMaxDelayChanged(u64),
+    RetryClassToggled(RetryClass, bool),
 
     // File/IO
     PickCookieFile, CookieFilePicked(Option<PathBuf>),
+    PickToolPath(Tool), ToolPathPicked(Tool, Option<PathBuf>), ClearToolPath(Tool),
+    ToolChannelChanged(Tool, UpdateChannel), PinnedVersionChanged(Tool, String), KeepBackupsChanged(usize), ToggleAllowUnverified(bool),
+    RollbackTool(Tool, PathBuf), ToolRolledBack(Tool, Result<String, String>),
     PickOutputDir, OutputDirPicked(Option<PathBuf>),
-    PickProxyList, ProxyListLoaded(Option<String>),
+    PickProxyList, ProxyListLoaded(Option<String>), ResetProxyHealth,
+    ProxyTestUrlChanged(String), CheckProxies, ProxiesChecked(Vec<(String, CheckOutcome)>), DropDeadProxies,
     
     // Download Control
     RetryDownload(usize), CancelDownload(usize),
+    PauseDownload(usize), ResumeDownload(usize),
+    CopyCommand(usize),
+    ToggleLogPanel(usize), CopyLog(usize), OpenLogFile(usize),
     Tick, CheckForUpdates,
+    WindowCloseRequested(iced::window::Id), CloseWindow(iced::window::Id),
     
     // Feedback
-    DownloadProgress(usize, f32, String, String),
+    DownloadProgress(usize, Progress),
+    DownloadLog(usize, String),
+    DownloadWarning(usize, String),
     DownloadFinished(usize),
-    DownloadFailed(usize, String),
+    DownloadFailed(usize, DownloadError),
+    DownloadStopped(usize, Result<Stopped, String>),
     
     // Removed unused install messages, kept ToolInstalled
     ToolInstalled(Result<String, String>),
-}
\ No newline at end of file
+    ToolInfoLoaded(Vec<(Tool, ToolInfo)>),
+}
\ No newline at end of file
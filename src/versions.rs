@@ -0,0 +1,184 @@
+use std::cmp::Ordering;
+use regex::Regex;
+use once_cell::sync::Lazy;
+
+static YTDLP_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^v?(\d{4})\.(\d{1,2})\.(\d{1,2})(?:\.(\d+))?$").unwrap()
+});
+static FFMPEG_OUTPUT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"ffmpeg version (\S+)").unwrap()
+});
+static FFMPEG_DATE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(\d{4})-(\d{2})-(\d{2})").unwrap()
+});
+static FFMPEG_RELEASE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^n?(\d+(?:\.\d+)*)").unwrap()
+});
+static FFMPEG_GIT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^N-(\d+)(?:-|$)").unwrap()
+});
+
+// yt-dlp versions are release dates; nightly builds add a fourth, time-of-build component, so a
+// nightly sorts after the stable release of the same day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct YtDlpVersion { pub year: u16, pub month: u8, pub day: u8, pub build: Option<u32> }
+
+impl YtDlpVersion {
+    pub fn parse(s: &str) -> Option<Self> {
+        let caps = YTDLP_RE.captures(s.trim())?;
+        Some(YtDlpVersion {
+            year: caps[1].parse().ok()?,
+            month: caps[2].parse().ok()?,
+            day: caps[3].parse().ok()?,
+            build: caps.get(4).and_then(|b| b.as_str().parse().ok()),
+        })
+    }
+}
+
+impl std::fmt::Display for YtDlpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{:02}.{:02}", self.year, self.month, self.day)?;
+        if let Some(b) = self.build { write!(f, ".{}", b)?; }
+        Ok(())
+    }
+}
+
+// ffmpeg builds name themselves three ways: a release number (`7.0.2-static`, `n7.1`), a snapshot
+// date (gyan.dev's `2024-08-04-git-eb3cc508d8-...`) or a commit count on master (`N-116466-g...`).
+// Only versions of the same kind can be ordered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FfmpegVersion { Release(Vec<u32>), Snapshot(u16, u8, u8), Git(u32) }
+
+impl FfmpegVersion {
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Some(c) = FFMPEG_DATE_RE.captures(s) {
+            return Some(FfmpegVersion::Snapshot(c[1].parse().ok()?, c[2].parse().ok()?, c[3].parse().ok()?));
+        }
+        if let Some(c) = FFMPEG_GIT_RE.captures(s) { return Some(FfmpegVersion::Git(c[1].parse().ok()?)); }
+        let c = FFMPEG_RELEASE_RE.captures(s)?;
+        Some(FfmpegVersion::Release(c[1].split('.').filter_map(|p| p.parse().ok()).collect()))
+    }
+
+    // Reads the version from `ffmpeg -version` output.
+    pub fn from_output(text: &str) -> Option<Self> { Self::parse(&FFMPEG_OUTPUT_RE.captures(text)?[1]) }
+
+    pub fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (FfmpegVersion::Release(a), FfmpegVersion::Release(b)) => {
+                // 7.1 and 7.1.0 are the same release
+                let len = a.len().max(b.len());
+                let pad = |v: &Vec<u32>| (0..len).map(|i| v.get(i).copied().unwrap_or(0)).collect::<Vec<_>>();
+                Some(pad(a).cmp(&pad(b)))
+            }
+            (FfmpegVersion::Snapshot(y1, m1, d1), FfmpegVersion::Snapshot(y2, m2, d2)) => Some((y1, m1, d1).cmp(&(y2, m2, d2))),
+            (FfmpegVersion::Git(a), FfmpegVersion::Git(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for FfmpegVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FfmpegVersion::Release(parts) => write!(f, "{}", parts.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(".")),
+            FfmpegVersion::Snapshot(y, m, d) => write!(f, "{}-{:02}-{:02}", y, m, d),
+            FfmpegVersion::Git(n) => write!(f, "N-{}", n),
+        }
+    }
+}
+
+// Pulls the latest version out of a release feed: GitHub's release JSON (`tag_name`), evermeet's
+// info JSON (`version`) or johnvansickle's release-readme (`version: 7.0.2`).
+pub fn latest_from_feed(body: &str) -> Option<String> {
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(body) {
+        return json.get("tag_name").or(json.get("version")).and_then(|v| v.as_str()).map(|s| s.trim().to_string());
+    }
+    body.lines().find_map(|l| l.trim().strip_prefix("version:")).map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ytdlp(s: &str) -> YtDlpVersion { YtDlpVersion::parse(s).unwrap_or_else(|| panic!("{}", s)) }
+    fn ffmpeg_output(version: &str) -> String {
+        format!("ffmpeg version {} Copyright (c) 2000-2024 the FFmpeg developers\nbuilt with gcc 13.2.0 (Rev5, Built by MSYS2 project)\nconfiguration: --enable-gpl --enable-version3\nlibavutil      59.  8.100 / 59.  8.100\n", version)
+    }
+    fn from_output(version: &str) -> FfmpegVersion { FfmpegVersion::from_output(&ffmpeg_output(version)).unwrap_or_else(|| panic!("{}", version)) }
+
+    #[test]
+    fn ytdlp_versions() {
+        assert_eq!(ytdlp("2024.08.06"), YtDlpVersion { year: 2024, month: 8, day: 6, build: None });
+        assert_eq!(ytdlp("2024.08.07.232751\n"), YtDlpVersion { year: 2024, month: 8, day: 7, build: Some(232751) });
+        assert_eq!(ytdlp("v2023.3.4"), YtDlpVersion { year: 2023, month: 3, day: 4, build: None });
+        assert_eq!(ytdlp("2024.08.07.232751").to_string(), "2024.08.07.232751");
+        assert_eq!(ytdlp("v2023.3.4").to_string(), "2023.03.04");
+        for bad in ["", "yt-dlp 2024.08.06", "2024.08", "nightly@2024.08.07", "2024-08-06"] { assert_eq!(YtDlpVersion::parse(bad), None, "{}", bad); }
+    }
+
+    #[test]
+    fn ytdlp_ordering() {
+        assert!(ytdlp("2024.08.06") > ytdlp("2024.07.25"));
+        assert!(ytdlp("2024.10.1") > ytdlp("2024.9.30"));
+        // A nightly sorts after the stable release of its day and before the next one
+        assert!(ytdlp("2024.08.06.232751") > ytdlp("2024.08.06"));
+        assert!(ytdlp("2024.08.06.232751") < ytdlp("2024.08.07"));
+        assert!(ytdlp("2024.08.06.100000") < ytdlp("2024.08.06.232751"));
+    }
+
+    #[test]
+    fn ffmpeg_builds_in_the_wild() {
+        // gyan.dev release and git builds (Windows)
+        assert_eq!(from_output("7.0.2-essentials_build-www.gyan.dev"), FfmpegVersion::Release(vec![7, 0, 2]));
+        assert_eq!(from_output("2024-08-04-git-eb3cc508d8-essentials_build-www.gyan.dev"), FfmpegVersion::Snapshot(2024, 8, 4));
+        // johnvansickle static (Linux) and evermeet (macOS)
+        assert_eq!(from_output("7.0.2-static"), FfmpegVersion::Release(vec![7, 0, 2]));
+        assert_eq!(from_output("7.1-tessus"), FfmpegVersion::Release(vec![7, 1]));
+        assert_eq!(from_output("N-116466-g6c1d6b2bb8-tessus"), FfmpegVersion::Git(116466));
+        // Distro packages and tags
+        assert_eq!(from_output("6.1.1-3ubuntu5"), FfmpegVersion::Release(vec![6, 1, 1]));
+        assert_eq!(from_output("n7.1"), FfmpegVersion::Release(vec![7, 1]));
+        assert_eq!(FfmpegVersion::from_output("ffprobe version 7.0.2"), None);
+        assert_eq!(FfmpegVersion::from_output("command not found"), None);
+    }
+
+    #[test]
+    fn ffmpeg_display() {
+        assert_eq!(from_output("7.0.2-static").to_string(), "7.0.2");
+        assert_eq!(from_output("2024-08-04-git-eb3cc508d8-full_build-www.gyan.dev").to_string(), "2024-08-04");
+        assert_eq!(from_output("N-116466-g6c1d6b2bb8").to_string(), "N-116466");
+        // What is shown is also what gets pinned and stored, so it has to parse back
+        for v in ["7.0.2-static", "2024-08-04-git-eb3cc508d8", "N-116466-g6c1d6b2bb8"] {
+            assert_eq!(FfmpegVersion::parse(&from_output(v).to_string()), Some(from_output(v)));
+        }
+    }
+
+    #[test]
+    fn ffmpeg_compare_only_within_a_kind() {
+        let v = |s: &str| FfmpegVersion::parse(s).unwrap();
+        assert_eq!(v("7.1").compare(&v("7.0.2")), Some(Ordering::Greater));
+        assert_eq!(v("7.1").compare(&v("7.1.0")), Some(Ordering::Equal));
+        assert_eq!(v("6.1.1").compare(&v("7.0")), Some(Ordering::Less));
+        assert_eq!(v("2024-08-04").compare(&v("2024-07-30")), Some(Ordering::Greater));
+        assert_eq!(v("N-116466").compare(&v("N-116500")), Some(Ordering::Less));
+        assert_eq!(v("7.0.2").compare(&v("2024-08-04")), None);
+        assert_eq!(v("2024-08-04").compare(&v("N-116466")), None);
+        assert_eq!(v("N-116466").compare(&v("7.0.2")), None);
+    }
+
+    #[test]
+    fn latest_from_each_feed() {
+        // GitHub releases API (yt-dlp, yt-dlp nightly, gyan.dev's codexffmpeg)
+        assert_eq!(latest_from_feed(r#"{"url":"https://api.github.com/repos/yt-dlp/yt-dlp/releases/169189373","tag_name":"2024.08.06","name":"yt-dlp 2024.08.06","draft":false}"#).as_deref(), Some("2024.08.06"));
+        assert_eq!(latest_from_feed(r#"{"tag_name":"7.0.2","name":"7.0.2","prerelease":false}"#).as_deref(), Some("7.0.2"));
+        // evermeet's info JSON
+        assert_eq!(latest_from_feed(r#"{"name":"ffmpeg","type":"release","version":"7.1","size":26,"download":{"zip":{"url":"https://evermeet.cx/ffmpeg/ffmpeg-7.1.zip"}}}"#).as_deref(), Some("7.1"));
+        // johnvansickle's release-readme.txt
+        let readme = "\n              ______ ______\n             / ____// ____/\n\n      build: ffmpeg-7.0.2-amd64-static.tar.xz\n    version: 7.0.2\n\n        gcc: 8.3.0\n       yasm: 1.3.0.36.ge2569\n";
+        assert_eq!(latest_from_feed(readme).as_deref(), Some("7.0.2"));
+        assert_eq!(latest_from_feed(r#"{"message":"API rate limit exceeded"}"#), None);
+        assert_eq!(latest_from_feed("<html>Not Found</html>"), None);
+        assert_eq!(latest_from_feed("version:\n"), None);
+    }
+}